@@ -0,0 +1,87 @@
+#import bevy_sprite::mesh2d_types
+#import bevy_sprite::mesh2d_view_bindings
+
+// Cells with the element, variant and strength in the red, green and blue channels
+@group(1) @binding(0)
+var cells: texture_2d<f32>;
+// A row of 256 gradient colors for each element
+@group(1) @binding(1)
+var palette: texture_2d<f32>;
+// Render method, full strength and pattern of each element
+@group(1) @binding(2)
+var elements: texture_2d<f32>;
+// Tiled patterns of 16x16 cells below each other, must match PATTERN_SIZE
+@group(1) @binding(3)
+var patterns: texture_2d<f32>;
+// Glow color and sky light (in alpha) for each block of 4x4 cells, must match LIGHT_SCALE
+@group(1) @binding(4)
+var light: texture_2d<f32>;
+@group(1) @binding(5)
+var light_sampler: sampler;
+
+// Must match the constants in lighting.rs
+const AMBIENT_LIGHT: f32 = 0.2;
+const BLOOM: f32 = 0.4;
+
+struct FragmentInput {
+    #import bevy_sprite::mesh2d_vertex_output
+};
+
+fn hash(value: u32) -> u32 {
+    var x = value;
+    x = x ^ (x >> 16u);
+    x = x * 0x7feb352du;
+    x = x ^ (x >> 15u);
+    x = x * 0x846ca68bu;
+    x = x ^ (x >> 16u);
+    return x;
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(cells));
+    let position = clamp(vec2<i32>(in.uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    let cell = textureLoad(cells, position, 0);
+    let element = u32(round(cell.r * 255.0));
+    let variant = round(cell.g * 255.0);
+    let strength = round(cell.b * 255.0);
+
+    let properties = round(textureLoad(elements, vec2<i32>(i32(element), 0), 0) * 255.0);
+    var factor = 255.0;
+    switch u32(properties.r) {
+        // Strength linear
+        case 1u: {
+            factor = strength / max(properties.g, 1.0) * 255.0;
+        }
+        // Variant linear
+        case 2u: {
+            factor = variant;
+        }
+        // Flicker, without state so different for each cell and frame
+        case 3u: {
+            let seed = u32(position.x) + u32(position.y) * u32(size.x) + globals.frame_count * 7919u;
+            factor = f32((u32(variant) + hash(seed)) % 255u);
+        }
+        // Texture, mostly the pattern with a little variation per cell
+        case 4u: {
+            let pattern_position = vec2<i32>(position.x % 16, position.y % 16 + i32(properties.b) * 16);
+            let pattern = round(textureLoad(patterns, pattern_position, 0).r * 255.0);
+            factor = floor((pattern * 3.0 + variant) / 4.0);
+        }
+        // Fixed color
+        default: {}
+    }
+    let color = textureLoad(palette, vec2<i32>(i32(clamp(round(factor), 0.0, 255.0)), i32(element)), 0).rgb;
+
+    let light_uv = in.uv * vec2<f32>(size) / (vec2<f32>(textureDimensions(light)) * 4.0);
+    let lighting = textureSample(light, light_sampler, light_uv);
+    let brightness = min(vec3<f32>(AMBIENT_LIGHT + lighting.a * (1.0 - AMBIENT_LIGHT)) + lighting.rgb, vec3<f32>(1.0));
+    let lit = min(color * brightness + lighting.rgb * BLOOM, vec3<f32>(1.0));
+    return vec4<f32>(srgb_to_linear(lit), 1.0);
+}
@@ -0,0 +1,49 @@
+use criterion::*;
+use falling_rust::element::Element;
+use falling_rust::pseudo_random::PseudoRandom;
+use falling_rust::render::render_cells;
+use falling_rust::sandbox::SandBox;
+use falling_rust::simulation::{simulation_step, Simulation};
+use falling_rust::theme::Theme;
+
+fn criterion_benchmark(criterion: &mut Criterion) {
+    let size = 256;
+    let mut random = PseudoRandom::new();
+    let mut pixels = vec![0; size * size * 4];
+
+    // Empty sandbox (should be fast)
+    let sandbox = SandBox::new(size, size);
+    criterion.bench_function("empty_render", |b| {
+        b.iter(|| render_cells(&sandbox, Theme::default(), &mut random, &mut pixels))
+    });
+
+    // Layers of elements with all the ways of coloring cells, after settling for a while
+    let mut sandbox = SandBox::new(size, size);
+    let layers = [
+        Element::Sand,
+        Element::Water,
+        Element::Rock,
+        Element::Wood,
+        Element::Oil,
+        Element::Fire,
+        Element::Lava,
+        Element::Iron,
+    ];
+    for (layer, element) in layers.into_iter().enumerate() {
+        for y in size / 4 + layer * 16..size / 4 + layer * 16 + 16 {
+            for x in 1..size - 1 {
+                sandbox.set_element(x, y, element, (x * y) as u32);
+            }
+        }
+    }
+    let mut simulation = Simulation::new();
+    for _ in 0..20 {
+        simulation_step(&mut simulation, &mut sandbox);
+    }
+    criterion.bench_function("mixed_render", |b| {
+        b.iter(|| render_cells(&sandbox, Theme::default(), &mut random, &mut pixels))
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -37,6 +37,48 @@ fn criterion_benchmark(criterion: &mut Criterion) {
     criterion.bench_function("burning_oil_simulation", |b| {
         b.iter(|| simulation_step(&mut simulation, &mut sandbox))
     });
+
+    // A large body of water falling down and spreading out, started over for every run
+    let size = 128;
+    let water = filled_sandbox(size, Element::Water);
+    criterion.bench_function("liquid_heavy_simulation", |b| {
+        b.iter_batched_ref(
+            || water.clone(),
+            |sandbox| {
+                for _ in 0..10 {
+                    simulation_step(&mut simulation, sandbox)
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    // A large heap of sand falling down and piling up, started over for every run
+    let sand = filled_sandbox(size, Element::Sand);
+    criterion.bench_function("powder_heavy_simulation", |b| {
+        b.iter_batched_ref(
+            || sand.clone(),
+            |sandbox| {
+                for _ in 0..10 {
+                    simulation_step(&mut simulation, sandbox)
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+// Sandbox with the upper half mostly filled with the element, with holes so it keeps moving
+fn filled_sandbox(size: usize, element: Element) -> SandBox {
+    let mut sandbox = SandBox::new(size, size);
+    for y in 1..size / 2 {
+        for x in 1..size - 1 {
+            if (x + y) % 7 != 0 {
+                sandbox.set_element(x, y, element, (x * y) as u32);
+            }
+        }
+    }
+    sandbox
 }
 
 criterion_group!(benches, criterion_benchmark);
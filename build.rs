@@ -0,0 +1,29 @@
+use std::{env, fs, path::Path};
+
+// Embed all language files from assets/lang, so adding a language needs no code changes
+fn main() {
+    let lang_dir = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("assets/lang");
+    println!("cargo:rerun-if-changed={}", lang_dir.display());
+
+    let mut files: Vec<_> = fs::read_dir(&lang_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect();
+    files.sort();
+
+    let mut code = String::from("pub static LANGUAGE_FILES: &[(&str, &str)] = &[\n");
+    for path in files {
+        println!("cargo:rerun-if-changed={}", path.display());
+        let language_code = path.file_stem().unwrap().to_str().unwrap();
+        code.push_str(&format!(
+            "    ({:?}, include_str!({:?})),\n",
+            language_code,
+            path.display().to_string()
+        ));
+    }
+    code.push_str("];\n");
+
+    let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("languages.rs");
+    fs::write(out_path, code).unwrap();
+}
@@ -0,0 +1,85 @@
+use std::{env, fs, process, sync::Arc};
+
+use falling_rust::{
+    render::render_colors,
+    sandbox::{SandBox, MAX_SANDBOX_SIZE},
+    scripting::{Script, ScriptedElements},
+    simulation::Simulation,
+    theme::Theme,
+    toolbox::ToolBox,
+};
+
+// Run a sandbox script without a window, e.g. for automated experiments:
+// headless <script.rhai> [sandbox size] [steps] [image.png]
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!(
+            "Usage: {} <script.rhai> [sandbox size] [steps] [image.png]",
+            args[0]
+        );
+        process::exit(1);
+    }
+    let size = match args.get(2).map(|size| size.parse::<usize>()) {
+        None => 256,
+        Some(Ok(size)) if (3..=MAX_SANDBOX_SIZE).contains(&size) => size,
+        Some(_) => {
+            eprintln!("The sandbox size has to be from 3 to {}", MAX_SANDBOX_SIZE);
+            process::exit(1);
+        }
+    };
+    let steps = args
+        .get(3)
+        .and_then(|steps| steps.parse().ok())
+        .unwrap_or(0);
+
+    let source = fs::read_to_string(&args[1]).unwrap_or_else(|error| {
+        eprintln!("Could not read {}: {}", args[1], error);
+        process::exit(1);
+    });
+    let compiled = Script::compile(&source)
+        .and_then(|script| Ok((script, ScriptedElements::compile(&source)?)));
+    let (mut script, elements) = compiled.unwrap_or_else(|error| {
+        eprintln!("Could not compile {}: {}", args[1], error);
+        process::exit(1);
+    });
+
+    let mut sandbox = SandBox::new(size, size);
+    let mut simulation = Simulation::new();
+    if let Some(elements) = elements {
+        simulation.custom_update = Some(Arc::new(elements));
+    }
+    let mut toolbox = ToolBox::default();
+    let mut result = script.run(&mut sandbox, &mut simulation, &mut toolbox);
+    for _ in 0..steps {
+        if result.is_err() {
+            break;
+        }
+        simulation.step = true;
+        falling_rust::simulation::simulation_step(&mut simulation, &mut sandbox);
+        result = script.on_step(
+            simulation.step_count,
+            &mut sandbox,
+            &mut simulation,
+            &mut toolbox,
+        );
+    }
+    if let Err(error) = result {
+        eprintln!("Script error: {}", error);
+        process::exit(1);
+    }
+
+    if let Some(path) = args.get(4) {
+        let (width, height) = (sandbox.width() as u32, sandbox.height() as u32);
+        let image = image::RgbaImage::from_raw(
+            width,
+            height,
+            render_colors(&mut sandbox, Theme::default()),
+        )
+        .expect("pixel buffer matches the sandbox size");
+        if let Err(error) = image.save(path) {
+            eprintln!("Could not write {}: {}", path, error);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::{env, process, thread, time::Duration, time::Instant};
+
+use falling_rust::{
+    network::{Server, DEFAULT_PORT},
+    sandbox::{SandBox, MAX_SANDBOX_SIZE},
+    simulation::{simulation_step, Simulation, BASE_STEPS_PER_SECOND},
+};
+
+// Run a sandbox that clients edit together, started with `falling-rust --connect <address>`:
+// server [address] [sandbox size]
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let address = args
+        .get(1)
+        .cloned()
+        .unwrap_or_else(|| format!("0.0.0.0:{}", DEFAULT_PORT));
+    let size = match args.get(2).map(|size| size.parse::<usize>()) {
+        None => 256,
+        Some(Ok(size)) if (3..=MAX_SANDBOX_SIZE).contains(&size) => size,
+        Some(_) => {
+            eprintln!("The sandbox size has to be from 3 to {}", MAX_SANDBOX_SIZE);
+            process::exit(1);
+        }
+    };
+
+    let mut server = Server::bind(&address).unwrap_or_else(|error| {
+        eprintln!("Could not listen on {}: {}", address, error);
+        process::exit(1);
+    });
+    println!("Listening on {}", address);
+
+    let mut sandbox = SandBox::new(size, size);
+    let mut simulation = Simulation::new();
+    let step_time = Duration::from_secs_f32(1.0 / BASE_STEPS_PER_SECOND);
+    let mut clients = 0;
+    loop {
+        let start = Instant::now();
+        server.receive(&mut sandbox);
+        simulation_step(&mut simulation, &mut sandbox);
+        server.send_changes(&sandbox);
+
+        if server.client_count() != clients {
+            clients = server.client_count();
+            println!("{} client(s) connected", clients);
+        }
+        thread::sleep(step_time.saturating_sub(start.elapsed()));
+    }
+}
@@ -0,0 +1,201 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use bevy_egui::EguiContexts;
+
+use crate::{
+    element::Element,
+    gui::{GuiMode, SandboxGui},
+    pointer_input::PointerInputState,
+    render::render_system,
+    sandbox::SandBox,
+    simulation::simulation_system,
+    toolbox::{Tool, ToolBox},
+    SystemOrderLabel,
+};
+
+// Color of the outline of the brush
+const OUTLINE_COLOR: [u8; 4] = [255, 255, 255, 200];
+// Color of the cells the fill tool would replace
+const FILL_COLOR: [u8; 4] = [255, 255, 255, 70];
+
+/// Shows the shape and size of the tool under the pointer, on top of the sandbox
+pub struct BrushPreviewPlugin;
+
+impl Plugin for BrushPreviewPlugin {
+    fn build(&self, app: &mut App) {
+        // Before rendering clears the changed chunks, which the fill preview depends on
+        app.add_startup_system(setup_brush_preview).add_system(
+            brush_preview_system
+                .after(SystemOrderLabel::PointerInput)
+                .after(simulation_system)
+                .before(render_system),
+        );
+    }
+}
+
+#[derive(Component, Default)]
+struct BrushPreview {
+    // Cell, tool and size of the brush outline currently shown, to only update it on changes
+    shown: Option<(usize, usize, Tool, usize)>,
+    // Cell, element and sandbox size of the fill preview currently shown, and the area it covers
+    fill: Option<(usize, usize, Element, usize, usize)>,
+    fill_area: (usize, usize, usize, usize),
+}
+
+fn setup_brush_preview(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    commands.spawn((
+        BrushPreview::default(),
+        SpriteBundle {
+            texture: images.add(preview_image(1, 1, vec![0; 4])),
+            transform: Transform::from_xyz(0.0, 0.0, 1.0),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+}
+
+fn preview_image(width: usize, height: usize, data: Vec<u8>) -> Image {
+    Image::new(
+        Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+// Image of the outline of the brush shape within its area
+fn brush_outline(
+    toolbox: &ToolBox,
+    x: usize,
+    y: usize,
+    area: (usize, usize, usize, usize),
+) -> Image {
+    let (x1, y1, x2, y2) = area;
+    let (width, height) = (x2 - x1, y2 - y1);
+    let inside = |cx: isize, cy: isize| {
+        cx >= x1 as isize
+            && cy >= y1 as isize
+            && cx < x2 as isize
+            && cy < y2 as isize
+            && toolbox.in_brush(x, y, cx as usize, cy as usize)
+    };
+    let mut data = vec![0; width * height * 4];
+    for cy in y1..y2 {
+        for cx in x1..x2 {
+            let (ix, iy) = (cx as isize, cy as isize);
+            let edge = inside(ix, iy)
+                && !(inside(ix - 1, iy)
+                    && inside(ix + 1, iy)
+                    && inside(ix, iy - 1)
+                    && inside(ix, iy + 1));
+            if edge {
+                let index = ((cx - x1) + (cy - y1) * width) * 4;
+                data[index..index + 4].copy_from_slice(&OUTLINE_COLOR);
+            }
+        }
+    }
+    preview_image(width, height, data)
+}
+
+// Image of the cells the fill tool would replace, and the area it covers
+fn fill_preview(
+    toolbox: &ToolBox,
+    sandbox: &SandBox,
+    x: usize,
+    y: usize,
+) -> (Image, (usize, usize, usize, usize)) {
+    let region = toolbox.fill_region(sandbox, x, y);
+    let (x1, y1, x2, y2) = region
+        .iter()
+        .fold((x, y, x + 1, y + 1), |(x1, y1, x2, y2), &(cx, cy)| {
+            (x1.min(cx), y1.min(cy), x2.max(cx + 1), y2.max(cy + 1))
+        });
+    let width = x2 - x1;
+    let mut data = vec![0; width * (y2 - y1) * 4];
+    for (cx, cy) in region {
+        let index = ((cx - x1) + (cy - y1) * width) * 4;
+        data[index..index + 4].copy_from_slice(&FILL_COLOR);
+    }
+    (preview_image(width, y2 - y1, data), (x1, y1, x2, y2))
+}
+
+// System updating the brush preview to the tool and the cell under the pointer
+fn brush_preview_system(
+    mut egui_contexts: EguiContexts,
+    pointer: Res<PointerInputState>,
+    toolbox: Res<ToolBox>,
+    gui: Res<SandboxGui>,
+    sandbox: Query<&SandBox>,
+    mut preview: Query<(
+        &mut BrushPreview,
+        &mut Transform,
+        &mut Visibility,
+        &Handle<Image>,
+    )>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let (Ok(sandbox), Ok((mut preview, mut transform, mut visibility, image_handle))) =
+        (sandbox.get_single(), preview.get_single_mut())
+    else {
+        return;
+    };
+    let (x, y) = (pointer.world_position.x, pointer.world_position.y);
+    let on_sandbox =
+        x > 0.0 && x < sandbox.width() as f32 && y > 0.0 && y < sandbox.height() as f32;
+    if !on_sandbox
+        || gui.mode == GuiMode::MoveView
+        || egui_contexts.ctx_mut().is_pointer_over_area()
+    {
+        // Changes while hidden are not tracked
+        preview.fill = None;
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    let (x, y) = (x.floor() as usize, y.floor() as usize);
+
+    let area = if toolbox.tool == Tool::Fill {
+        preview.shown = None;
+        // The region only changes with the cells in it or next to it
+        let (x1, y1, x2, y2) = preview.fill_area;
+        let changed =
+            sandbox.is_area_dirty(x1.saturating_sub(1), y1.saturating_sub(1), x2 + 1, y2 + 1);
+        let fill = Some((x, y, toolbox.element, sandbox.width(), sandbox.height()));
+        if preview.fill != fill || changed {
+            preview.fill = fill;
+            let (image, area) = fill_preview(&toolbox, sandbox, x, y);
+            preview.fill_area = area;
+            if let Some(preview_image) = images.get_mut(image_handle) {
+                *preview_image = image;
+            }
+        }
+        preview.fill_area
+    } else {
+        preview.fill = None;
+        let area = toolbox.brush_area(sandbox, x, y);
+        if area.0 >= area.2 || area.1 >= area.3 {
+            *visibility = Visibility::Hidden;
+            return;
+        }
+        let shown = Some((x, y, toolbox.tool, toolbox.tool_size));
+        if preview.shown != shown {
+            preview.shown = shown;
+            let image = brush_outline(&toolbox, x, y, area);
+            if let Some(preview_image) = images.get_mut(image_handle) {
+                *preview_image = image;
+            }
+        }
+        area
+    };
+    *visibility = Visibility::Visible;
+
+    // Center the image on its area, the sandbox is centered on the origin with y pointing up
+    let (x1, y1, x2, y2) = area;
+    transform.translation.x = (x1 + x2) as f32 / 2.0 - sandbox.width() as f32 / 2.0;
+    transform.translation.y = sandbox.height() as f32 / 2.0 - (y1 + y2) as f32 / 2.0;
+}
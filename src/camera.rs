@@ -0,0 +1,127 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::{sandbox::SandBox, SystemOrderLabel};
+
+// Smallest camera scale, so the most zoomed in
+pub const MIN_ZOOM: f32 = 0.05;
+// Largest camera scale that can be stored, the actual limit depends on the window size
+pub const MAX_STORED_ZOOM: f32 = 16.0;
+// Largest camera scale without a sandbox, which limits the chunks of an endless world in view
+const MAX_FREE_ZOOM: f32 = 2.0;
+// Scale change of a single zoom step
+const ZOOM_STEP: f32 = 1.1;
+// How quickly the zoom animation approaches the target, per second
+const ZOOM_SPEED: f32 = 15.0;
+
+/// Animates the camera zoom and keeps the sandbox in view
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraZoom>()
+            .add_system(camera_system.after(SystemOrderLabel::PointerInput));
+    }
+}
+
+#[derive(Resource)]
+pub struct CameraZoom {
+    // Camera scale to animate towards, smaller is zoomed in
+    pub target: f32,
+    // Position in the window that stays in place while zooming, the center when None
+    anchor: Option<Vec2>,
+    fit: bool,
+}
+
+impl Default for CameraZoom {
+    fn default() -> Self {
+        CameraZoom {
+            target: 1.0,
+            anchor: None,
+            fit: false,
+        }
+    }
+}
+
+impl CameraZoom {
+    pub fn zoom_in(&mut self, anchor: Option<Vec2>) {
+        self.zoom_by(1.0 / ZOOM_STEP, anchor);
+    }
+
+    pub fn zoom_out(&mut self, anchor: Option<Vec2>) {
+        self.zoom_by(ZOOM_STEP, anchor);
+    }
+
+    // Multiply the camera scale, values below 1 zoom in
+    pub fn zoom_by(&mut self, factor: f32, anchor: Option<Vec2>) {
+        self.target *= factor;
+        self.anchor = anchor;
+    }
+
+    // Zoom so the whole sandbox fits in the window, and center it
+    pub fn fit_to_window(&mut self) {
+        self.fit = true;
+    }
+}
+
+fn camera_system(
+    mut zoom: ResMut<CameraZoom>,
+    time: Res<Time>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut camera: Query<&mut Transform, With<Camera>>,
+    sandbox: Query<&SandBox>,
+) {
+    let (Ok(window), Ok(mut transform)) = (window.get_single(), camera.get_single_mut()) else {
+        return;
+    };
+    let window_size = Vec2::new(window.width(), window.height());
+    if window_size.min_element() < 1.0 {
+        // Minimized
+        return;
+    }
+    // Without a sandbox, e.g. in an endless world, there is nothing to fit
+    let sandbox_size = sandbox
+        .get_single()
+        .ok()
+        .map(|sandbox| Vec2::new(sandbox.width() as f32, sandbox.height() as f32));
+    let fit_zoom = sandbox_size.map_or(1.0, |size| (size / window_size).max_element());
+
+    if zoom.fit {
+        zoom.fit = false;
+        zoom.target = fit_zoom;
+        zoom.anchor = None;
+        transform.translation.x = 0.0;
+        transform.translation.y = 0.0;
+    }
+    // Allow zooming out until the sandbox covers a quarter of the window
+    let max_zoom = match sandbox_size {
+        Some(_) => (fit_zoom * 2.0).max(1.0),
+        None => MAX_FREE_ZOOM,
+    };
+    zoom.target = zoom.target.clamp(MIN_ZOOM, max_zoom);
+
+    // Smoothly approach the target scale
+    let scale = transform.scale.x;
+    let mut new_scale =
+        scale + (zoom.target - scale) * (1.0 - (-ZOOM_SPEED * time.delta_seconds()).exp());
+    if (new_scale - zoom.target).abs() < zoom.target * 0.001 {
+        new_scale = zoom.target;
+    }
+    if new_scale != scale {
+        // Keep the world position under the anchor in place
+        if let Some(anchor) = zoom.anchor {
+            let offset = anchor - window_size / 2.0;
+            transform.translation.x += offset.x * (scale - new_scale);
+            transform.translation.y += offset.y * (scale - new_scale);
+        }
+        transform.scale.x = new_scale;
+        transform.scale.y = new_scale;
+    }
+
+    let Some(sandbox_size) = sandbox_size else {
+        return;
+    };
+    // Pan no further than the sandbox edges when zoomed in, or keep it fully visible when zoomed out
+    let limit = (sandbox_size / 2.0 - window_size / 2.0 * new_scale).abs();
+    transform.translation.x = transform.translation.x.clamp(-limit.x, limit.x);
+    transform.translation.y = transform.translation.y.clamp(-limit.y, limit.y);
+}
@@ -1,7 +1,7 @@
 use crate::element::*;
 
 // A cell that contains the state of a single pixel in the sand box.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Cell {
     // Element in this cell
     pub element: Element,
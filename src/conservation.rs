@@ -0,0 +1,80 @@
+use crate::element::{Element, ELEMENT_COUNT};
+
+// Accounting of the mass per element, showing which reactions create or destroy mass. Every cell
+// other than air counts as a unit of mass.
+#[derive(Clone, Debug)]
+pub struct MassBalance {
+    // Undo the updates of cells that would create or destroy mass
+    pub prevent: bool,
+    // Change in the number of cells of each element (second index) caused by updating the cells
+    // of each element (first index) in the last step
+    changes: [[i32; ELEMENT_COUNT]; ELEMENT_COUNT],
+    // Number of updates undone per element in the last step
+    prevented: [u32; ELEMENT_COUNT],
+    // Mass created per element since the accounting started, negative when destroyed
+    totals: [i64; ELEMENT_COUNT],
+}
+
+impl MassBalance {
+    pub fn new(prevent: bool) -> Self {
+        MassBalance {
+            prevent,
+            changes: [[0; ELEMENT_COUNT]; ELEMENT_COUNT],
+            prevented: [0; ELEMENT_COUNT],
+            totals: [0; ELEMENT_COUNT],
+        }
+    }
+
+    pub(crate) fn start_step(&mut self) {
+        self.changes = [[0; ELEMENT_COUNT]; ELEMENT_COUNT];
+        self.prevented = [0; ELEMENT_COUNT];
+    }
+
+    // Account for the changes made by updating a cell, returns false when they should be undone
+    pub(crate) fn update(&mut self, cause: Element, changes: &[i32; ELEMENT_COUNT]) -> bool {
+        let created = mass_of(changes);
+        if created != 0 && self.prevent {
+            self.prevented[cause as usize] += 1;
+            return false;
+        }
+        for (total, change) in self.changes[cause as usize].iter_mut().zip(changes) {
+            *total += change;
+        }
+        self.totals[cause as usize] += created as i64;
+        true
+    }
+
+    // Change in the number of cells per element caused by updating cells of an element
+    pub fn changes(&self, cause: Element) -> &[i32; ELEMENT_COUNT] {
+        &self.changes[cause as usize]
+    }
+
+    // Mass created by updating cells of an element in the last step, negative when destroyed
+    pub fn created(&self, cause: Element) -> i32 {
+        mass_of(&self.changes[cause as usize])
+    }
+
+    // Mass created by updating cells of an element since the accounting started
+    pub fn total_created(&self, cause: Element) -> i64 {
+        self.totals[cause as usize]
+    }
+
+    // Number of updates of cells of an element undone in the last step
+    pub fn prevented(&self, cause: Element) -> u32 {
+        self.prevented[cause as usize]
+    }
+
+    // Change in the number of cells of an element in the last step
+    pub fn element_change(&self, element: Element) -> i32 {
+        self.changes
+            .iter()
+            .map(|changes| changes[element as usize])
+            .sum()
+    }
+}
+
+// Every cell that changes turns from one element into another, so the mass created is the air
+// that is gone
+fn mass_of(changes: &[i32; ELEMENT_COUNT]) -> i32 {
+    -changes[Element::Air as usize]
+}
@@ -1,6 +1,6 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
 
-pub const ELEMENT_COUNT: usize = 26;
+pub const ELEMENT_COUNT: usize = 30;
 
 // The different element types that live in a cell in the sand box
 #[repr(u8)]
@@ -32,8 +32,20 @@ pub enum Element {
     FireSource = 23,
     LavaSource = 24,
     Indestructible = 25,
+    // Elements without built-in behaviour, for use by element scripts
+    Custom1 = 26,
+    Custom2 = 27,
+    Custom3 = 28,
+    Custom4 = 29,
 }
 
+pub const CUSTOM_ELEMENTS: [Element; 4] = [
+    Element::Custom1,
+    Element::Custom2,
+    Element::Custom3,
+    Element::Custom4,
+];
+
 pub const FLAG_DISSOLVES_IN_ACID: u32 = 0b00000000000000000000000000000001;
 pub const FLAG_BURNS: u32 = 0b00000000000000000000000000000010;
 pub const FLAG_CAUSES_RUST: u32 = 0b00000000000000000000000000000100;
@@ -54,9 +66,14 @@ pub struct ElementType {
     pub weight: u8,
     pub color_1: (u8, u8, u8),
     pub color_2: (u8, u8, u8),
+    // Gradient of colors from low to high values, just color_2 to color_1 when empty
+    pub palette: &'static [(u8, u8, u8)],
     pub render: RenderMethod,
     pub flags: u32,
     pub source_element: Element,
+    // Color and reach (in cells) of the light given off, elements with radius 0 do not glow
+    pub light: (u8, u8, u8),
+    pub light_radius: u8,
 }
 
 impl ElementType {
@@ -79,9 +96,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 128,
         color_1: (33, 122, 238),
         color_2: (0, 0, 0),
+        palette: &[],
         render: RenderMethod::FixedColor,
         flags: FLAG_ALLOW_PLANT,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, //  Sand = 1,
     ElementType {
         form: ElementForm::Powder,
@@ -89,9 +109,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (229, 184, 125),
         color_2: (156, 97, 41),
+        palette: &[],
         render: RenderMethod::VariantLinear,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_NUTRITIOUS | FLAG_ALLOW_PLANT,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Rock = 2,
     ElementType {
         form: ElementForm::Solid,
@@ -99,9 +122,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (178, 178, 178),
         color_2: (63, 63, 63),
-        render: RenderMethod::VariantLinear,
+        palette: &[],
+        render: RenderMethod::Texture(Pattern::Brick),
         flags: FLAG_BLAST_RESISTANT,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Water = 3,
     ElementType {
         form: ElementForm::Liquid,
@@ -109,9 +135,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 128,
         color_1: (16, 16, 128),
         color_2: (12, 12, 100),
+        palette: &[],
         render: RenderMethod::VariantLinear,
         flags: FLAG_CAUSES_RUST | FLAG_WET | FLAG_ALLOW_PLANT,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Acid = 4,
     ElementType {
         form: ElementForm::Liquid,
@@ -119,9 +148,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 32,
         color_1: (182, 255, 5),
         color_2: (5, 255, 40),
+        palette: &[],
         render: RenderMethod::VariantLinear,
         flags: FLAG_ACIDIC,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Drain = 5,
     ElementType {
         form: ElementForm::Solid,
@@ -129,9 +161,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (0, 0, 0),
         color_2: (0, 0, 0),
+        palette: &[],
         render: RenderMethod::FixedColor,
         flags: 0,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Wood = 6,
     ElementType {
         form: ElementForm::Solid,
@@ -139,9 +174,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (176, 110, 56),
         color_2: (73, 38, 22),
-        render: RenderMethod::VariantLinear,
+        palette: &[],
+        render: RenderMethod::Texture(Pattern::Grain),
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_BURNS | FLAG_TURNS_INTO_ASH | FLAG_BLAST_RESISTANT,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Iron = 7,
     ElementType {
         form: ElementForm::Solid,
@@ -149,9 +187,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (152, 148, 139),
         color_2: (100, 100, 90),
+        palette: &[],
         render: RenderMethod::VariantLinear,
         flags: FLAG_BLAST_RESISTANT,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Rust = 8,
     ElementType {
         form: ElementForm::Powder,
@@ -159,9 +200,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (115, 50, 2),
         color_2: (60, 40, 2),
+        palette: &[],
         render: RenderMethod::VariantLinear,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_CAUSES_RUST,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Fire = 9,
     ElementType {
         form: ElementForm::Gas,
@@ -169,9 +213,17 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 64,
         color_1: (255, 255, 159),
         color_2: (158, 61, 18),
+        palette: &[
+            (158, 61, 18),
+            (230, 110, 20),
+            (255, 190, 60),
+            (255, 255, 159),
+        ],
         render: RenderMethod::Flicker,
         flags: FLAG_IGNITES,
         source_element: Element::Air,
+        light: (255, 140, 40),
+        light_radius: 12,
     }, // Ash = 10,
     ElementType {
         form: ElementForm::Powder,
@@ -179,9 +231,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (214, 220, 234),
         color_2: (124, 124, 136),
+        palette: &[],
         render: RenderMethod::StrengthLinear,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_NUTRITIOUS | FLAG_ALLOW_PLANT,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Oil = 11,
     ElementType {
         form: ElementForm::Liquid,
@@ -189,9 +244,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 64,
         color_1: (64, 32, 64),
         color_2: (32, 16, 32),
+        palette: &[],
         render: RenderMethod::VariantLinear,
         flags: FLAG_BURNS,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Lava = 12,
     ElementType {
         form: ElementForm::Liquid,
@@ -199,9 +257,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 192,
         color_1: (180, 64, 16),
         color_2: (90, 32, 8),
+        palette: &[(90, 32, 8), (140, 40, 10), (180, 64, 16), (230, 120, 30)],
         render: RenderMethod::VariantLinear,
         flags: FLAG_IGNITES,
         source_element: Element::Air,
+        light: (255, 90, 20),
+        light_radius: 10,
     }, // Smoke = 13,
     ElementType {
         form: ElementForm::Gas,
@@ -209,9 +270,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 32,
         color_1: (8, 8, 8),
         color_2: (33, 122, 238),
+        palette: &[],
         render: RenderMethod::StrengthLinear,
         flags: 0,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Life = 14,
     ElementType {
         form: ElementForm::Solid,
@@ -219,9 +283,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (210, 255, 210),
         color_2: (105, 128, 105),
+        palette: &[],
         render: RenderMethod::StrengthLinear,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_BURNS | FLAG_TURNS_INTO_ASH,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Seed = 15,
     ElementType {
         form: ElementForm::Powder,
@@ -229,9 +296,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (170, 220, 130),
         color_2: (0, 0, 0),
+        palette: &[],
         render: RenderMethod::FixedColor,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_NUTRITIOUS,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Plant = 16,
     ElementType {
         form: ElementForm::Solid,
@@ -239,9 +309,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (60, 200, 30),
         color_2: (10, 30, 5),
+        palette: &[],
         render: RenderMethod::VariantLinear,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_BURNS | FLAG_NUTRITIOUS,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // TNT = 17,
     ElementType {
         form: ElementForm::Solid,
@@ -249,9 +322,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (200, 32, 16),
         color_2: (180, 24, 8),
+        palette: &[],
         render: RenderMethod::VariantLinear,
         flags: FLAG_DISSOLVES_IN_ACID,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Fuse = 18,
     ElementType {
         form: ElementForm::Solid,
@@ -259,9 +335,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (127, 51, 0),
         color_2: (0, 0, 0),
+        palette: &[],
         render: RenderMethod::FixedColor,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_BURNS,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // Explosion = 19,
     ElementType {
         form: ElementForm::Solid,
@@ -269,9 +348,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (245, 220, 200),
         color_2: (255, 255, 255),
+        palette: &[(255, 255, 255), (255, 240, 170), (245, 220, 200)],
         render: RenderMethod::Flicker,
         flags: 0,
         source_element: Element::Air,
+        light: (255, 220, 120),
+        light_radius: 24,
     }, // WaterSource = 20,
     ElementType {
         form: ElementForm::Solid,
@@ -279,9 +361,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (16, 16, 255),
         color_2: (0, 0, 0),
+        palette: &[],
         render: RenderMethod::FixedColor,
         flags: FLAG_IS_SOURCE,
         source_element: Element::Water,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // AcidSource = 21,
     ElementType {
         form: ElementForm::Solid,
@@ -289,9 +374,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (160, 255, 64),
         color_2: (0, 0, 0),
+        palette: &[],
         render: RenderMethod::FixedColor,
         flags: FLAG_IS_SOURCE,
         source_element: Element::Acid,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // OilSource = 22,
     ElementType {
         form: ElementForm::Solid,
@@ -299,9 +387,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (32, 8, 32),
         color_2: (0, 0, 0),
+        palette: &[],
         render: RenderMethod::FixedColor,
         flags: FLAG_IS_SOURCE,
         source_element: Element::Oil,
+        light: (0, 0, 0),
+        light_radius: 0,
     }, // FireSource = 23,
     ElementType {
         form: ElementForm::Solid,
@@ -309,9 +400,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (255, 255, 16),
         color_2: (0, 0, 0),
+        palette: &[],
         render: RenderMethod::FixedColor,
         flags: FLAG_IS_SOURCE | FLAG_IGNITES,
         source_element: Element::Fire,
+        light: (255, 140, 40),
+        light_radius: 12,
     }, // LavaSource = 24,
     ElementType {
         form: ElementForm::Solid,
@@ -319,9 +413,12 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (255, 128, 32),
         color_2: (0, 0, 0),
+        palette: &[],
         render: RenderMethod::FixedColor,
         flags: FLAG_IS_SOURCE,
         source_element: Element::Lava,
+        light: (255, 90, 20),
+        light_radius: 10,
     }, // Indestructible = 25,
     ElementType {
         form: ElementForm::Solid,
@@ -329,9 +426,64 @@ pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
         weight: 1,
         color_1: (64, 40, 40),
         color_2: (0, 0, 0),
+        palette: &[],
         render: RenderMethod::FixedColor,
         flags: 0,
         source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
+    }, // Custom1 = 26,
+    ElementType {
+        form: ElementForm::Solid,
+        strength: 16,
+        weight: 1,
+        color_1: (230, 90, 200),
+        color_2: (150, 40, 130),
+        palette: &[],
+        render: RenderMethod::VariantLinear,
+        flags: 0,
+        source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
+    }, // Custom2 = 27,
+    ElementType {
+        form: ElementForm::Solid,
+        strength: 16,
+        weight: 1,
+        color_1: (90, 230, 220),
+        color_2: (30, 140, 140),
+        palette: &[],
+        render: RenderMethod::VariantLinear,
+        flags: 0,
+        source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
+    }, // Custom3 = 28,
+    ElementType {
+        form: ElementForm::Solid,
+        strength: 16,
+        weight: 1,
+        color_1: (240, 240, 90),
+        color_2: (150, 150, 30),
+        palette: &[],
+        render: RenderMethod::VariantLinear,
+        flags: 0,
+        source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
+    }, // Custom4 = 29,
+    ElementType {
+        form: ElementForm::Solid,
+        strength: 16,
+        weight: 1,
+        color_1: (250, 250, 250),
+        color_2: (160, 160, 170),
+        palette: &[],
+        render: RenderMethod::VariantLinear,
+        flags: 0,
+        source_element: Element::Air,
+        light: (0, 0, 0),
+        light_radius: 0,
     },
 ];
 
@@ -349,6 +501,24 @@ pub enum RenderMethod {
     StrengthLinear,
     VariantLinear,
     Flicker,
+    // Tiled pattern by position, with a little variation per cell
+    Texture(Pattern),
+}
+
+impl RenderMethod {
+    // Whether cells get a random variant when placed
+    pub fn uses_variant(&self) -> bool {
+        matches!(
+            self,
+            RenderMethod::VariantLinear | RenderMethod::Flicker | RenderMethod::Texture(_)
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    Brick,
+    Grain,
 }
 
 impl fmt::Display for Element {
@@ -356,3 +526,55 @@ impl fmt::Display for Element {
         write!(f, "{:?}", self)
     }
 }
+
+impl TryFrom<u8> for Element {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Element::Air),
+            1 => Ok(Element::Sand),
+            2 => Ok(Element::Rock),
+            3 => Ok(Element::Water),
+            4 => Ok(Element::Acid),
+            5 => Ok(Element::Drain),
+            6 => Ok(Element::Wood),
+            7 => Ok(Element::Iron),
+            8 => Ok(Element::Rust),
+            9 => Ok(Element::Fire),
+            10 => Ok(Element::Ash),
+            11 => Ok(Element::Oil),
+            12 => Ok(Element::Lava),
+            13 => Ok(Element::Smoke),
+            14 => Ok(Element::Life),
+            15 => Ok(Element::Seed),
+            16 => Ok(Element::Plant),
+            17 => Ok(Element::TNT),
+            18 => Ok(Element::Fuse),
+            19 => Ok(Element::Explosion),
+            20 => Ok(Element::WaterSource),
+            21 => Ok(Element::AcidSource),
+            22 => Ok(Element::OilSource),
+            23 => Ok(Element::FireSource),
+            24 => Ok(Element::LavaSource),
+            25 => Ok(Element::Indestructible),
+            26 => Ok(Element::Custom1),
+            27 => Ok(Element::Custom2),
+            28 => Ok(Element::Custom3),
+            29 => Ok(Element::Custom4),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for Element {
+    type Err = ();
+
+    // Parse an element from its name, as written by Display
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        (0..ELEMENT_COUNT as u8)
+            .filter_map(|value| Element::try_from(value).ok())
+            .find(|element| element.to_string() == s)
+            .ok_or(())
+    }
+}
@@ -0,0 +1,363 @@
+use bevy::{ecs::system::SystemParam, prelude::*, window::PrimaryWindow};
+use bevy_egui::{
+    egui::{self, Color32, Pos2, Stroke},
+    EguiContexts,
+};
+
+use crate::{
+    element::{Element, CUSTOM_ELEMENTS},
+    network::{RemoteTools, ToolApplication},
+    pointer_input::{cell_position, PointerInputState},
+    sandbox::SandBox,
+    settings::Settings,
+    simulation::Simulation,
+    theme::gradient_color,
+    toolbox::{ToolBox, EDITOR_ELEMENTS},
+    SystemOrderLabel,
+};
+
+// Speed of the virtual cursor with the stick fully pushed, in pixels per second
+const CURSOR_SPEED: f32 = 500.0;
+// Speed of moving the view with the stick fully pushed, in pixels per second
+const PAN_SPEED: f32 = 600.0;
+// Change of the tool size per press on the d-pad
+const TOOL_SIZE_STEP: usize = 2;
+// Cursor colors of the players, the first is the mouse
+pub const PLAYER_COLORS: [(u8, u8, u8); 4] = [
+    (255, 255, 255),
+    (255, 90, 90),
+    (90, 160, 255),
+    (255, 220, 60),
+];
+
+/// Controls a virtual cursor and the tools with gamepads, shared with the mouse or one per player
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GamepadCursor>()
+            .init_resource::<GamepadPlayers>()
+            .add_systems(
+                (gamepad_cursor_system, gamepad_tool_system)
+                    .distributive_run_if(shared_cursor)
+                    .before(SystemOrderLabel::PointerInput),
+            )
+            .add_system(
+                gamepad_player_system
+                    .run_if(local_multiplayer)
+                    .before(SystemOrderLabel::PointerInput),
+            )
+            .add_system(gamepad_camera_system.before(SystemOrderLabel::PointerInput))
+            .add_system(draw_gamepad_cursors);
+    }
+}
+
+// Virtual cursor moved by the left stick, in window coordinates like the mouse
+#[derive(Resource, Default)]
+pub struct GamepadCursor {
+    pub position: Vec2,
+    // Shown while the gamepad is used, hidden when the mouse moves
+    pub visible: bool,
+}
+
+// A player with a gamepad, with tools and a cursor of their own
+pub struct GamepadPlayer {
+    pub gamepad: Gamepad,
+    pub toolbox: ToolBox,
+    pub cursor: Vec2,
+    pub color: (u8, u8, u8),
+}
+
+// Players in local multiplayer, in the order their gamepads were connected
+#[derive(Resource, Default)]
+pub struct GamepadPlayers {
+    pub players: Vec<GamepadPlayer>,
+}
+
+fn local_multiplayer(settings: Res<Settings>) -> bool {
+    settings.local_multiplayer
+}
+
+fn shared_cursor(settings: Res<Settings>) -> bool {
+    !settings.local_multiplayer
+}
+
+#[derive(SystemParam)]
+struct GamepadInput<'w> {
+    gamepads: Res<'w, Gamepads>,
+    buttons: Res<'w, Input<GamepadButton>>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+}
+
+// State of the cursor controls of one gamepad
+#[derive(Default)]
+struct CursorControls {
+    movement: Vec2,
+    draw: bool,
+    erase: bool,
+    released: bool,
+}
+
+impl GamepadInput<'_> {
+    fn stick(&self, gamepad: Gamepad, x: GamepadAxisType, y: GamepadAxisType) -> Vec2 {
+        Vec2::new(
+            self.axes.get(GamepadAxis::new(gamepad, x)).unwrap_or(0.0),
+            self.axes.get(GamepadAxis::new(gamepad, y)).unwrap_or(0.0),
+        )
+    }
+
+    fn just_pressed(&self, gamepad: Gamepad, button_type: GamepadButtonType) -> bool {
+        self.buttons
+            .just_pressed(GamepadButton::new(gamepad, button_type))
+    }
+
+    // Left stick moves, the right trigger draws and the left trigger erases
+    fn cursor_controls(&self, gamepad: Gamepad) -> CursorControls {
+        let draw_button = GamepadButton::new(gamepad, GamepadButtonType::RightTrigger2);
+        let erase_button = GamepadButton::new(gamepad, GamepadButtonType::LeftTrigger2);
+        CursorControls {
+            movement: self.stick(
+                gamepad,
+                GamepadAxisType::LeftStickX,
+                GamepadAxisType::LeftStickY,
+            ),
+            draw: self.buttons.pressed(draw_button),
+            erase: self.buttons.pressed(erase_button),
+            released: self.buttons.just_released(draw_button)
+                || self.buttons.just_released(erase_button),
+        }
+    }
+
+    // Shoulder buttons select the element, the north button the tool and the d-pad the size
+    fn change_tool(&self, gamepad: Gamepad, toolbox: &mut ToolBox, simulation: &Simulation) {
+        let next = self.just_pressed(gamepad, GamepadButtonType::RightTrigger);
+        if next || self.just_pressed(gamepad, GamepadButtonType::LeftTrigger) {
+            toolbox.cycle_element(&editor_elements(simulation), next);
+        }
+        if self.just_pressed(gamepad, GamepadButtonType::North) {
+            toolbox.cycle_tool();
+        }
+        if self.just_pressed(gamepad, GamepadButtonType::DPadUp) {
+            toolbox.tool_size = (toolbox.tool_size + TOOL_SIZE_STEP).min(64);
+        }
+        if self.just_pressed(gamepad, GamepadButtonType::DPadDown) {
+            toolbox.tool_size = toolbox.tool_size.saturating_sub(TOOL_SIZE_STEP).max(1);
+        }
+    }
+}
+
+// Elements that can be selected, custom elements only when a script defines their behaviour
+fn editor_elements(simulation: &Simulation) -> Vec<Element> {
+    let mut elements = EDITOR_ELEMENTS.to_vec();
+    if let Some(custom_update) = &simulation.custom_update {
+        elements.extend(
+            CUSTOM_ELEMENTS
+                .into_iter()
+                .filter(|element| custom_update.defines(*element)),
+        );
+    }
+    elements
+}
+
+fn move_cursor(position: Vec2, movement: Vec2, delta_seconds: f32, window: &Window) -> Vec2 {
+    let window_size = Vec2::new(window.width(), window.height());
+    (position + movement * CURSOR_SPEED * delta_seconds).clamp(Vec2::ZERO, window_size)
+}
+
+// System moving the cursor shared with the mouse, and drawing or erasing through the pointer input
+fn gamepad_cursor_system(
+    input: GamepadInput,
+    time: Res<Time>,
+    mut cursor: ResMut<GamepadCursor>,
+    mut pointer: ResMut<PointerInputState>,
+    window: Query<&Window, With<PrimaryWindow>>,
+) {
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+    if cursor.visible && pointer.position != cursor.position {
+        // The mouse moved, it takes over the pointer
+        cursor.visible = false;
+    }
+
+    let mut controls = CursorControls::default();
+    for gamepad in input.gamepads.iter() {
+        let gamepad_controls = input.cursor_controls(gamepad);
+        controls.movement += gamepad_controls.movement;
+        controls.draw |= gamepad_controls.draw;
+        controls.erase |= gamepad_controls.erase;
+        controls.released |= gamepad_controls.released;
+    }
+    if controls.movement == Vec2::ZERO && !controls.draw && !controls.erase && !controls.released {
+        return;
+    }
+
+    if !cursor.visible {
+        // Continue from where the mouse was
+        cursor.position = pointer.position;
+        cursor.visible = true;
+    }
+    cursor.position = move_cursor(
+        cursor.position,
+        controls.movement,
+        time.delta_seconds(),
+        window,
+    );
+    pointer.position = cursor.position;
+    pointer.left_button_down = controls.draw;
+    pointer.right_button_down = controls.erase && !controls.draw;
+}
+
+// System changing the tools shared with the mouse
+fn gamepad_tool_system(
+    input: GamepadInput,
+    simulation: Res<Simulation>,
+    mut toolbox: ResMut<ToolBox>,
+) {
+    for gamepad in input.gamepads.iter() {
+        input.change_tool(gamepad, &mut toolbox, &simulation);
+    }
+}
+
+// System letting every gamepad edit the sandbox with its own cursor and tools
+fn gamepad_player_system(
+    input: GamepadInput,
+    time: Res<Time>,
+    simulation: Res<Simulation>,
+    mut players: ResMut<GamepadPlayers>,
+    camera: Query<(&Camera, &GlobalTransform)>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    (mut sandbox, mut remote_tools): (Query<&mut SandBox>, Option<ResMut<RemoteTools>>),
+) {
+    let (Ok((camera, global_transform)), Ok(window), Ok(mut sandbox)) = (
+        camera.get_single(),
+        window.get_single(),
+        sandbox.get_single_mut(),
+    ) else {
+        return;
+    };
+
+    // Players join by connecting a gamepad, and leave by disconnecting it
+    players
+        .players
+        .retain(|player| input.gamepads.contains(player.gamepad));
+    for gamepad in input.gamepads.iter() {
+        if players
+            .players
+            .iter()
+            .any(|player| player.gamepad == gamepad)
+        {
+            continue;
+        }
+        // The first color not in use, or repeat them when there are many players
+        let color = PLAYER_COLORS[1..]
+            .iter()
+            .find(|color| players.players.iter().all(|player| player.color != **color))
+            .unwrap_or(&PLAYER_COLORS[1 + players.players.len() % (PLAYER_COLORS.len() - 1)]);
+        players.players.push(GamepadPlayer {
+            gamepad,
+            toolbox: ToolBox::default(),
+            cursor: Vec2::new(window.width(), window.height()) / 2.0,
+            color: *color,
+        });
+    }
+
+    for player in players.players.iter_mut() {
+        input.change_tool(player.gamepad, &mut player.toolbox, &simulation);
+        let controls = input.cursor_controls(player.gamepad);
+        player.cursor = move_cursor(
+            player.cursor,
+            controls.movement,
+            time.delta_seconds(),
+            window,
+        );
+        if !controls.draw && !controls.erase {
+            continue;
+        }
+        let position = cell_position(camera, global_transform, &sandbox, player.cursor);
+        let (x, y) = (position.x, position.y);
+        if x > 0.0 && x < sandbox.width() as f32 && y > 0.0 && y < sandbox.height() as f32 {
+            let (x, y) = (x.floor() as usize, y.floor() as usize);
+            if let Some(remote_tools) = remote_tools.as_mut() {
+                // Applied when it comes back from the network, like the tools of the mouse
+                remote_tools.0.push(ToolApplication {
+                    tool: player.toolbox.tool,
+                    element: if controls.draw {
+                        player.toolbox.element
+                    } else {
+                        Element::Air
+                    },
+                    tool_size: player.toolbox.tool_size,
+                    x,
+                    y,
+                });
+            } else if controls.draw {
+                player.toolbox.apply(&mut sandbox, x, y);
+            } else {
+                player.toolbox.erase(&mut sandbox, x, y);
+            }
+        }
+    }
+}
+
+// System moving the view with the right stick, the camera system keeps it within limits
+fn gamepad_camera_system(
+    input: GamepadInput,
+    time: Res<Time>,
+    mut camera: Query<&mut Transform, With<Camera>>,
+) {
+    let Ok(mut transform) = camera.get_single_mut() else {
+        return;
+    };
+    for gamepad in input.gamepads.iter() {
+        let movement = input.stick(
+            gamepad,
+            GamepadAxisType::RightStickX,
+            GamepadAxisType::RightStickY,
+        ) * PAN_SPEED
+            * time.delta_seconds();
+        transform.translation.x += movement.x * transform.scale.x;
+        transform.translation.y += movement.y * transform.scale.y;
+    }
+}
+
+// System drawing the virtual cursors on top of everything, in the color of the player,
+// sized like the tool and with the color of the element in the center
+fn draw_gamepad_cursors(
+    mut egui_contexts: EguiContexts,
+    settings: Res<Settings>,
+    cursor: Res<GamepadCursor>,
+    players: Res<GamepadPlayers>,
+    toolbox: Res<ToolBox>,
+    camera: Query<&Transform, With<Camera>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+) {
+    let (Ok(transform), Ok(window)) = (camera.get_single(), window.get_single()) else {
+        return;
+    };
+    let painter = egui_contexts.ctx_mut().layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("gamepad_cursors"),
+    ));
+    let draw_cursor = |position: Vec2, toolbox: &ToolBox, (r, g, b): (u8, u8, u8)| {
+        // The cursor starts at the bottom of the window, egui at the top
+        let center = Pos2::new(position.x, window.height() - position.y);
+        let radius = (toolbox.tool_size as f32 / 2.0 / transform.scale.x).max(4.0);
+        let element = gradient_color(settings.theme.gradient(toolbox.element), 255, 255);
+        painter.circle_stroke(center, radius + 1.0, Stroke::new(1.0, Color32::BLACK));
+        painter.circle_stroke(center, radius, Stroke::new(1.0, Color32::from_rgb(r, g, b)));
+        painter.circle_filled(
+            center,
+            2.5,
+            Color32::from_rgb(element.0, element.1, element.2),
+        );
+    };
+
+    if settings.local_multiplayer {
+        for player in players.players.iter() {
+            draw_cursor(player.cursor, &player.toolbox, player.color);
+        }
+    } else if cursor.visible {
+        draw_cursor(cursor.position, &toolbox, PLAYER_COLORS[0]);
+    }
+}
@@ -0,0 +1,200 @@
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    reflect::TypeUuid,
+    render::render_resource::{AsBindGroup, Extent3d, ShaderRef, TextureDimension, TextureFormat},
+    sprite::{Material2d, Material2dPlugin, Mesh2dHandle},
+};
+
+use crate::{
+    element::{element_type, Element, RenderMethod, ELEMENT_COUNT},
+    lighting::LIGHT_MAP_HANDLE,
+    render::{render_system, DebugView, RenderState},
+    sandbox::SandBox,
+    settings::Settings,
+    theme::{gradient_color, pattern_images, Theme, PATTERN_SIZE},
+};
+
+const CELL_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 8126499436734159261);
+const PALETTE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Image::TYPE_UUID, 2301938475610293847);
+
+/// Renders the sandbox with a shader that colors the raw cells, instead of coloring them on the CPU
+pub struct GpuRenderPlugin;
+
+impl Plugin for GpuRenderPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            CELL_SHADER_HANDLE,
+            "../assets/shaders/cells.wgsl",
+            Shader::from_wgsl
+        );
+        app.world
+            .resource_mut::<Assets<Image>>()
+            .set_untracked(PALETTE_HANDLE, element_palette(Theme::default()));
+        app.add_plugin(Material2dPlugin::<CellMaterial>::default())
+            .add_system(palette_system)
+            .add_system(render_mode_system.before(render_system));
+    }
+}
+
+// Material that looks up the color of each cell in the palette of its element
+#[derive(AsBindGroup, TypeUuid, Debug, Clone)]
+#[uuid = "5b3c8f4e-9f0d-4a55-8d3a-7c1e2b6f9a10"]
+pub struct CellMaterial {
+    #[texture(0, filterable = false)]
+    cells: Handle<Image>,
+    #[texture(1, filterable = false)]
+    palette: Handle<Image>,
+    #[texture(2, filterable = false)]
+    elements: Handle<Image>,
+    #[texture(3, filterable = false)]
+    patterns: Handle<Image>,
+    #[texture(4)]
+    #[sampler(5)]
+    light: Handle<Image>,
+}
+
+impl Material2d for CellMaterial {
+    fn fragment_shader() -> ShaderRef {
+        CELL_SHADER_HANDLE.typed().into()
+    }
+}
+
+// Texture with a row of 256 gradient colors for each element in the theme
+fn element_palette(theme: Theme) -> Image {
+    let mut data = Vec::with_capacity(ELEMENT_COUNT * 256 * 4);
+    for value in 0..ELEMENT_COUNT as u8 {
+        let gradient = match Element::try_from(value) {
+            Ok(element) => theme.gradient(element),
+            Err(_) => &[(0, 0, 0)],
+        };
+        for factor in 0..=u8::MAX {
+            let (r, g, b) = gradient_color(gradient, factor, u8::MAX);
+            data.extend_from_slice(&[r, g, b, 255]);
+        }
+    }
+    texture(256, ELEMENT_COUNT, data)
+}
+
+// Texture with the render method, full strength and pattern of each element
+fn element_properties() -> Image {
+    let mut data = Vec::with_capacity(ELEMENT_COUNT * 4);
+    for value in 0..ELEMENT_COUNT as u8 {
+        let properties = match Element::try_from(value) {
+            Ok(element) => {
+                let element_type = element_type(element);
+                let (render, pattern) = match element_type.render {
+                    RenderMethod::FixedColor => (0, 0),
+                    RenderMethod::StrengthLinear => (1, 0),
+                    RenderMethod::VariantLinear => (2, 0),
+                    RenderMethod::Flicker => (3, 0),
+                    RenderMethod::Texture(pattern) => (4, pattern as u8),
+                };
+                [render, element_type.strength, pattern, 255]
+            }
+            Err(_) => [0, 0, 0, 255],
+        };
+        data.extend_from_slice(&properties);
+    }
+    texture(ELEMENT_COUNT, 1, data)
+}
+
+// Texture with all patterns below each other, in the red channel
+fn patterns() -> Image {
+    let patterns = pattern_images();
+    let data = patterns
+        .iter()
+        .flatten()
+        .flat_map(|value| [*value, 0, 0, 255])
+        .collect();
+    texture(PATTERN_SIZE, PATTERN_SIZE * patterns.len(), data)
+}
+
+fn texture(width: usize, height: usize, data: Vec<u8>) -> Image {
+    Image::new(
+        Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8Unorm,
+    )
+}
+
+// System updating the palette when the theme changes
+fn palette_system(
+    settings: Res<Settings>,
+    mut theme: Local<Theme>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if settings.theme != *theme {
+        *theme = settings.theme;
+        images.set_untracked(PALETTE_HANDLE, element_palette(*theme));
+    }
+}
+
+// Whether the sandbox image holds raw cells for the shader instead of colors
+pub fn is_gpu_rendered(image: &Image) -> bool {
+    image.texture_descriptor.format == TextureFormat::Rgba8Unorm
+}
+
+type SandboxImage<'a> = (
+    Entity,
+    &'a mut SandBox,
+    &'a Handle<Image>,
+    Option<&'a Handle<CellMaterial>>,
+);
+
+// System switching the sandbox between the shader and plain sprite, depending on the settings.
+// Debug views are only available when coloring the cells on the CPU.
+fn render_mode_system(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    render_state: Res<RenderState>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<CellMaterial>>,
+    mut sandbox: Query<SandboxImage>,
+) {
+    let Ok((entity, mut sandbox, image_handle, material)) = sandbox.get_single_mut() else {
+        return;
+    };
+    let use_gpu = settings.gpu_render && render_state.debug_view == DebugView::None;
+    match (use_gpu, material) {
+        (true, None) => {
+            let Some(image) = images.get_mut(image_handle) else {
+                return;
+            };
+            image.texture_descriptor.format = TextureFormat::Rgba8Unorm;
+            // The image holds colors until all cells are copied to it
+            sandbox.mark_all_dirty();
+            let size = image.size();
+            commands
+                .entity(entity)
+                .remove::<Sprite>()
+                .insert(Mesh2dHandle(meshes.add(shape::Quad::new(size).into())))
+                .insert(materials.add(CellMaterial {
+                    cells: image_handle.clone(),
+                    palette: PALETTE_HANDLE.typed(),
+                    elements: images.add(element_properties()),
+                    patterns: images.add(patterns()),
+                    light: LIGHT_MAP_HANDLE.typed(),
+                }));
+        }
+        (false, Some(_)) => {
+            if let Some(image) = images.get_mut(image_handle) {
+                image.texture_descriptor.format = TextureFormat::Rgba8UnormSrgb;
+            }
+            commands
+                .entity(entity)
+                .remove::<(Mesh2dHandle, Handle<CellMaterial>)>()
+                .insert(Sprite::default());
+        }
+        _ => {}
+    }
+}
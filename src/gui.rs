@@ -5,21 +5,30 @@ use bevy_egui::{
     egui::{self, style::*, Color32, ColorImage, Frame, Layout, Response, TextureHandle, Ui},
     EguiContexts, EguiPlugin,
 };
-use egui::{Align2, FontId, Mesh, Pos2, Rect, Shape, Vec2};
+use egui::{
+    plot::{Bar, BarChart, Legend, Line, Plot, PlotPoints},
+    Align2, FontId, Mesh, Pos2, Rect, Shape, Vec2,
+};
 use image::{DynamicImage, GenericImageView};
 
 const ICON_SIZE: f32 = 64.0;
 
 use crate::{
+    camera::CameraZoom,
+    conservation::MassBalance,
     element::*,
+    history::History,
     language::{element_names, get_text, Language},
+    network::NetworkClient,
     pseudo_random::PseudoRandom,
-    render::cell_color,
+    render::{cell_color, DebugView, RenderState},
     sandbox::SandBox,
     settings::Settings,
     simulation::Simulation,
     spawn_sandbox,
-    toolbox::{Tool, ToolBox},
+    statistics::Statistics,
+    theme::Theme,
+    toolbox::{Tool, ToolBox, EDITOR_ELEMENTS},
     SystemOrderLabel,
 };
 
@@ -45,6 +54,8 @@ pub enum GuiMode {
 #[derive(Resource)]
 pub struct SandboxGui {
     pub mode: GuiMode,
+    pub show_statistics: bool,
+    pub show_debug: bool,
     pub last_element: Element,
     pub bucket_icon_handle: TextureHandle,
     pub icon_circle_handle: TextureHandle,
@@ -56,6 +67,7 @@ pub struct SandboxGui {
     pub icon_pause_handle: TextureHandle,
     pub icon_zoom_in_handle: TextureHandle,
     pub icon_zoom_out_handle: TextureHandle,
+    pub icon_fit_handle: TextureHandle,
     pub icon_move_handle: TextureHandle,
     pub icon_settings_handle: TextureHandle,
     pub icon_eraser_handle: TextureHandle,
@@ -67,23 +79,57 @@ pub struct SandboxGui {
 // Simple GUI for use both in desktop and touchscreen (via web) applications
 pub fn gui_system(
     mut egui_contexts: EguiContexts,
-    mut camera: Query<&mut Transform, With<Camera>>,
+    mut camera_zoom: ResMut<CameraZoom>,
     mut gui: ResMut<SandboxGui>,
     settings: ResMut<Settings>,
     mut toolbox: ResMut<ToolBox>,
     mut simulation: ResMut<Simulation>,
-    sandbox: Query<(Entity, &mut SandBox)>,
+    mut history: ResMut<History>,
+    statistics: Res<Statistics>,
+    mut render_state: ResMut<RenderState>,
+    mut sandbox: Query<(Entity, &mut SandBox)>,
     commands: Commands,
     images: ResMut<Assets<Image>>,
+    client: Option<Res<NetworkClient>>,
 ) {
     side_panel_right(
         &mut egui_contexts,
         &mut gui,
         &mut simulation,
-        camera.single_mut().as_mut(),
+        &mut camera_zoom,
     );
 
-    bottom_panel(&mut egui_contexts, &mut gui, &mut toolbox);
+    bottom_panel(
+        &mut egui_contexts,
+        &mut gui,
+        &mut toolbox,
+        &mut history,
+        &mut simulation,
+        &mut sandbox,
+    );
+
+    if gui.show_statistics {
+        statistics_window(
+            &mut egui_contexts,
+            &mut gui,
+            &statistics,
+            &mut simulation,
+            &settings,
+        );
+    }
+
+    if gui.show_debug {
+        debug_window(
+            &mut egui_contexts,
+            &mut gui,
+            &mut render_state,
+            &mut simulation,
+            settings.language,
+        );
+    } else if render_state.debug_view != DebugView::None {
+        render_state.debug_view = DebugView::None;
+        simulation.update_costs = None;
+    }
 
     if gui.mode == GuiMode::SandboxSettings {
         side_panel_left_settings(
@@ -92,11 +138,12 @@ pub fn gui_system(
             settings,
             commands,
             images,
-            &simulation,
+            &mut simulation,
             &mut gui,
+            client.is_some(),
         );
     } else if gui.mode == GuiMode::ElementSelect {
-        side_panel_left_select_element(&mut egui_contexts, &mut gui, &mut toolbox);
+        side_panel_left_select_element(&mut egui_contexts, &mut gui, &mut toolbox, &simulation);
     } else if gui.mode == GuiMode::ToolSelect {
         side_panel_left_tool_select(egui_contexts, gui, toolbox);
     }
@@ -196,6 +243,7 @@ fn side_panel_left_select_element(
     egui_contexts: &mut EguiContexts,
     gui: &mut ResMut<SandboxGui>,
     toolbox: &mut ResMut<ToolBox>,
+    simulation: &Simulation,
 ) {
     egui::CentralPanel::default()
         .frame(Frame::none())
@@ -207,74 +255,110 @@ fn side_panel_left_select_element(
                 )
                 .with_main_wrap(true),
                 |ui| {
-                    element_button_click(ui, gui, Element::Sand, toolbox);
-                    element_button_click(ui, gui, Element::Wood, toolbox);
-                    element_button_click(ui, gui, Element::Iron, toolbox);
-                    element_button_click(ui, gui, Element::Rock, toolbox);
-                    element_button_click(ui, gui, Element::Water, toolbox);
-                    element_button_click(ui, gui, Element::Acid, toolbox);
-                    element_button_click(ui, gui, Element::Oil, toolbox);
-                    element_button_click(ui, gui, Element::Lava, toolbox);
-                    element_button_click(ui, gui, Element::Fire, toolbox);
-                    element_button_click(ui, gui, Element::Life, toolbox);
-                    element_button_click(ui, gui, Element::Seed, toolbox);
-                    element_button_click(ui, gui, Element::TNT, toolbox);
-                    element_button_click(ui, gui, Element::Fuse, toolbox);
-                    element_button_click(ui, gui, Element::WaterSource, toolbox);
-                    element_button_click(ui, gui, Element::AcidSource, toolbox);
-                    element_button_click(ui, gui, Element::LavaSource, toolbox);
-                    element_button_click(ui, gui, Element::FireSource, toolbox);
-                    element_button_click(ui, gui, Element::Drain, toolbox);
+                    for element in EDITOR_ELEMENTS {
+                        element_button_click(ui, gui, element, toolbox);
+                    }
+                    // Custom elements are only useful when a script defines their behaviour
+                    if let Some(custom_update) = &simulation.custom_update {
+                        for element in CUSTOM_ELEMENTS {
+                            if custom_update.defines(element) {
+                                element_button_click(ui, gui, element, toolbox);
+                            }
+                        }
+                    }
                 },
             );
         });
 }
 
 // World settings panel
+#[allow(clippy::too_many_arguments)]
 fn side_panel_left_settings(
     egui_contexts: &mut EguiContexts,
     mut sandbox: Query<(Entity, &mut SandBox)>,
     mut settings: ResMut<Settings>,
     mut commands: Commands,
     mut images: ResMut<Assets<Image>>,
-    simulation: &Simulation,
+    simulation: &mut Simulation,
     gui: &mut ResMut<SandboxGui>,
+    connected: bool,
 ) {
     egui::SidePanel::left("settings").show(egui_contexts.ctx_mut(), |ui| {
-        let (entity, sandbox) = sandbox.single_mut();
-        egui::ComboBox::from_label(get_text("size", settings.language))
-            .selected_text(format!(
-                "{}x{}",
-                settings.sandbox_size, settings.sandbox_size
-            ))
-            .show_ui(ui, |ui| {
-                ui.selectable_value(&mut settings.sandbox_size, 64, "64x64");
-                ui.selectable_value(&mut settings.sandbox_size, 128, "128x128");
-                ui.selectable_value(&mut settings.sandbox_size, 256, "256x256");
-                ui.selectable_value(&mut settings.sandbox_size, 512, "512x512");
-                ui.selectable_value(&mut settings.sandbox_size, 1024, "1024x1024");
-            });
-        if ui.button(get_text("new", settings.language)).clicked() {
-            commands.entity(entity).despawn();
-            spawn_sandbox(
-                commands,
-                images.as_mut(),
-                settings.sandbox_size,
-                settings.sandbox_size,
-            );
-            gui.mode = GuiMode::MainGui;
+        let sandbox = sandbox.get_single_mut().ok();
+        // An endless world has no size, and the server decides the size of a shared sandbox
+        if let Some((entity, _)) = sandbox.as_ref().filter(|_| !connected) {
+            egui::ComboBox::from_label(get_text("size", settings.language))
+                .selected_text(format!(
+                    "{}x{}",
+                    settings.sandbox_size, settings.sandbox_size
+                ))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut settings.sandbox_size, 64, "64x64");
+                    ui.selectable_value(&mut settings.sandbox_size, 128, "128x128");
+                    ui.selectable_value(&mut settings.sandbox_size, 256, "256x256");
+                    ui.selectable_value(&mut settings.sandbox_size, 512, "512x512");
+                    ui.selectable_value(&mut settings.sandbox_size, 1024, "1024x1024");
+                });
+            if ui.button(get_text("new", settings.language)).clicked() {
+                commands.entity(*entity).despawn();
+                spawn_sandbox(
+                    commands,
+                    images.as_mut(),
+                    settings.sandbox_size,
+                    settings.sandbox_size,
+                );
+                gui.mode = GuiMode::MainGui;
+            }
+            ui.separator();
         }
-        ui.separator();
         let previous_language = settings.language;
         egui::ComboBox::from_label(get_text("language", settings.language))
-            .selected_text(format!("{:?}", settings.language))
+            .selected_text(settings.language.name())
             .show_ui(ui, |ui| {
-                ui.selectable_value(&mut settings.language, Language::English, "English");
-                ui.selectable_value(&mut settings.language, Language::Nederlands, "Nederlands");
+                for language in Language::all() {
+                    ui.selectable_value(&mut settings.language, language, language.name());
+                }
             });
         if settings.language != previous_language {
             gui.element_names = element_names(settings.language);
         }
+        let language = settings.language;
+        ui.checkbox(&mut settings.autosave, get_text("autosave", language));
+        ui.checkbox(&mut gui.show_statistics, get_text("statistics", language));
+        ui.checkbox(&mut settings.gpu_render, get_text("gpu_render", language));
+        ui.checkbox(&mut settings.lighting, get_text("lighting", language));
+        ui.checkbox(
+            &mut settings.local_multiplayer,
+            get_text("local_multiplayer", language),
+        );
+        let previous_theme = settings.theme;
+        egui::ComboBox::from_label(get_text("theme", language))
+            .selected_text(get_text(settings.theme.text_key(), language))
+            .show_ui(ui, |ui| {
+                for theme in Theme::ALL {
+                    ui.selectable_value(
+                        &mut settings.theme,
+                        theme,
+                        get_text(theme.text_key(), language),
+                    );
+                }
+            });
+        if settings.theme != previous_theme {
+            gui.element_icons = generate_element_icons(ui.ctx(), settings.theme);
+        }
+        ui.checkbox(&mut gui.show_debug, get_text("debug", language));
+        ui.separator();
+        egui::ComboBox::from_label(get_text("speed", language))
+            .selected_text(speed_text(simulation.speed))
+            .show_ui(ui, |ui| {
+                for speed in [0.125, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0] {
+                    ui.selectable_value(&mut simulation.speed, speed, speed_text(speed));
+                }
+            });
+        ui.checkbox(
+            &mut simulation.fixed_timestep,
+            get_text("fixed_timestep", language),
+        );
         ui.separator();
         ui.label(format!(
             "{}: {} ms",
@@ -282,19 +366,188 @@ fn side_panel_left_settings(
             simulation.frame_time_ms
         ));
         ui.label(format!(
-            "{}: {} ms",
-            get_text("render", settings.language),
-            sandbox.render_time_ms
+            "{}: {:.0}",
+            get_text("steps_per_second", settings.language),
+            simulation.steps_per_second
         ));
+        if let Some((_, sandbox)) = sandbox.as_ref() {
+            ui.label(format!(
+                "{}: {} ms",
+                get_text("render", settings.language),
+                sandbox.render_time_ms
+            ));
+        }
         ui.separator();
         ui.hyperlink_to("Made by Bas", "https://www.basvs.dev");
     });
 }
 
+// Window with the current population of each element and its history
+fn statistics_window(
+    egui_contexts: &mut EguiContexts,
+    gui: &mut ResMut<SandboxGui>,
+    statistics: &Statistics,
+    simulation: &mut Simulation,
+    settings: &Settings,
+) {
+    let language = settings.language;
+    let Some(counts) = statistics.latest() else {
+        return;
+    };
+    // Skip air and the border, which would dwarf the rest
+    let elements: Vec<Element> = (0..ELEMENT_COUNT as u8)
+        .filter_map(|value| Element::try_from(value).ok())
+        .filter(|element| *element != Element::Air && *element != Element::Indestructible)
+        .filter(|element| {
+            statistics
+                .samples()
+                .any(|(_, counts)| counts[*element as usize] > 0)
+        })
+        .collect();
+    let color = |element: Element| {
+        let gradient = settings.theme.gradient(element);
+        let (r, g, b) = gradient[gradient.len() - 1];
+        Color32::from_rgb(r, g, b)
+    };
+
+    let mut open = gui.show_statistics;
+    egui::Window::new(get_text("statistics", language))
+        .open(&mut open)
+        .default_size([400.0, 400.0])
+        .show(egui_contexts.ctx_mut(), |ui| {
+            let bars = elements
+                .iter()
+                .enumerate()
+                .map(|(index, element)| {
+                    Bar::new(index as f64, counts[*element as usize] as f64)
+                        .name(&gui.element_names[element])
+                        .fill(color(*element))
+                })
+                .collect();
+            Plot::new("population_histogram")
+                .height(150.0)
+                .show_x(false)
+                .show(ui, |plot_ui| plot_ui.bar_chart(BarChart::new(bars)));
+            Plot::new("population_graph")
+                .height(200.0)
+                .legend(Legend::default())
+                .show(ui, |plot_ui| {
+                    for element in elements.iter() {
+                        let points: PlotPoints = statistics
+                            .samples()
+                            .map(|(step, counts)| [*step as f64, counts[*element as usize] as f64])
+                            .collect();
+                        plot_ui.line(
+                            Line::new(points)
+                                .name(&gui.element_names[element])
+                                .color(color(*element)),
+                        );
+                    }
+                });
+            ui.separator();
+            conservation_ui(ui, gui, simulation, language);
+        });
+    gui.show_statistics = open;
+}
+
+// Switches for the mass accounting and the mass created or destroyed per reaction
+fn conservation_ui(ui: &mut Ui, gui: &SandboxGui, simulation: &mut Simulation, language: Language) {
+    let mut track = simulation.mass_balance.is_some();
+    ui.checkbox(&mut track, get_text("conservation", language));
+    if track != simulation.mass_balance.is_some() {
+        simulation.mass_balance = track.then(|| MassBalance::new(false));
+    }
+    let Some(mass_balance) = simulation.mass_balance.as_mut() else {
+        return;
+    };
+    ui.checkbox(
+        &mut mass_balance.prevent,
+        get_text("conservation.prevent", language),
+    );
+    let mut balanced = true;
+    for element in (0..ELEMENT_COUNT as u8).filter_map(|value| Element::try_from(value).ok()) {
+        let (created, total) = (
+            mass_balance.created(element),
+            mass_balance.total_created(element),
+        );
+        let prevented = mass_balance.prevented(element);
+        if created == 0 && total == 0 && prevented == 0 {
+            continue;
+        }
+        balanced = false;
+        let mut text = format!(
+            "{}: {:+} ({} {:+})",
+            gui.element_names[&element],
+            created,
+            get_text("conservation.total", language),
+            total
+        );
+        if prevented > 0 {
+            text += &format!(
+                ", {} {}",
+                prevented,
+                get_text("conservation.prevented", language)
+            );
+        }
+        ui.label(text);
+    }
+    if balanced {
+        ui.label(get_text("conservation.balanced", language));
+    }
+}
+
+// Window to pick a debug view that shows the inner workings of the simulation
+fn debug_window(
+    egui_contexts: &mut EguiContexts,
+    gui: &mut ResMut<SandboxGui>,
+    render_state: &mut RenderState,
+    simulation: &mut Simulation,
+    language: Language,
+) {
+    let mut open = gui.show_debug;
+    egui::Window::new(get_text("debug", language))
+        .open(&mut open)
+        .show(egui_contexts.ctx_mut(), |ui| {
+            for view in DebugView::ALL {
+                ui.radio_value(
+                    &mut render_state.debug_view,
+                    view,
+                    get_text(view.text_key(), language),
+                );
+            }
+            if let Some(update_costs) = simulation.update_costs.as_ref() {
+                let max = update_costs.iter().max().copied().unwrap_or_default();
+                ui.label(format!(
+                    "{}: {} ns",
+                    get_text("debug.max_update_cost", language),
+                    max
+                ));
+            }
+        });
+    gui.show_debug = open;
+
+    // Measuring the update cost of each cell slows down the simulation, so only do it when shown
+    let measure = render_state.debug_view == DebugView::UpdateCost;
+    if measure != simulation.update_costs.is_some() {
+        simulation.update_costs = measure.then(Vec::new);
+    }
+}
+
+fn speed_text(speed: f32) -> String {
+    if speed < 1.0 {
+        format!("1/{}x", (1.0 / speed).round())
+    } else {
+        format!("{}x", speed)
+    }
+}
+
 fn bottom_panel(
     egui_contexts: &mut EguiContexts,
     gui: &mut ResMut<SandboxGui>,
     toolbox: &mut ResMut<ToolBox>,
+    history: &mut ResMut<History>,
+    simulation: &mut ResMut<Simulation>,
+    sandbox: &mut Query<(Entity, &mut SandBox)>,
 ) {
     egui::TopBottomPanel::bottom("bottom_panel")
         .frame(Frame::none())
@@ -351,6 +604,18 @@ fn bottom_panel(
                         gui.mode = GuiMode::ToolSelect;
                     }
                 };
+
+                // Timeline to rewind the simulation to an earlier snapshot
+                if history.len() > 1 {
+                    let mut position = history.position();
+                    let timeline =
+                        egui::Slider::new(&mut position, 0..=history.len() - 1).show_value(false);
+                    if ui.add(timeline).changed() {
+                        if let Ok((_, mut sandbox)) = sandbox.get_single_mut() {
+                            history.restore(position, &mut sandbox, simulation);
+                        }
+                    }
+                }
             });
         });
 }
@@ -359,7 +624,7 @@ fn side_panel_right(
     egui_contexts: &mut EguiContexts,
     gui: &mut ResMut<SandboxGui>,
     simulation: &mut ResMut<Simulation>,
-    transform: &mut Transform,
+    camera_zoom: &mut CameraZoom,
 ) {
     egui::SidePanel::right("right_panel")
         .frame(Frame::none())
@@ -423,8 +688,7 @@ fn side_panel_right(
                 )
                 .clicked()
             {
-                transform.scale.x = (transform.scale.x * 0.9).clamp(0.1, 1.0);
-                transform.scale.y = (transform.scale.y * 0.9).clamp(0.1, 1.0);
+                camera_zoom.zoom_in(None);
             };
             if ui
                 .add(
@@ -436,8 +700,16 @@ fn side_panel_right(
                 )
                 .clicked()
             {
-                transform.scale.x = (transform.scale.x * 1.1).clamp(0.1, 1.0);
-                transform.scale.y = (transform.scale.y * 1.1).clamp(0.1, 1.0);
+                camera_zoom.zoom_out(None);
+            };
+            if ui
+                .add(
+                    egui::widgets::ImageButton::new(&gui.icon_fit_handle, [ICON_SIZE, ICON_SIZE])
+                        .frame(false),
+                )
+                .clicked()
+            {
+                camera_zoom.fit_to_window();
             };
             let move_button =
                 egui::widgets::ImageButton::new(&gui.icon_move_handle, [ICON_SIZE, ICON_SIZE])
@@ -466,41 +738,14 @@ fn setup_gui(mut commands: Commands, mut egui_contexts: EguiContexts, settings:
     style.spacing.button_padding = bevy_egui::egui::Vec2::new(10.0, 10.0);
     egui_contexts.ctx_mut().set_style(style);
 
-    // Generate element icons
-    let background = image::load_from_memory(include_bytes!("../assets/icon_element.png")).unwrap();
-    let element_icons = [
-        generate_element_image(Element::Air, &mut egui_contexts, &background),
-        generate_element_image(Element::Sand, &mut egui_contexts, &background),
-        generate_element_image(Element::Rock, &mut egui_contexts, &background),
-        generate_element_image(Element::Water, &mut egui_contexts, &background),
-        generate_element_image(Element::Acid, &mut egui_contexts, &background),
-        generate_element_image(Element::Drain, &mut egui_contexts, &background),
-        generate_element_image(Element::Wood, &mut egui_contexts, &background),
-        generate_element_image(Element::Iron, &mut egui_contexts, &background),
-        generate_element_image(Element::Rust, &mut egui_contexts, &background),
-        generate_element_image(Element::Fire, &mut egui_contexts, &background),
-        generate_element_image(Element::Ash, &mut egui_contexts, &background),
-        generate_element_image(Element::Oil, &mut egui_contexts, &background),
-        generate_element_image(Element::Lava, &mut egui_contexts, &background),
-        generate_element_image(Element::Smoke, &mut egui_contexts, &background),
-        generate_element_image(Element::Life, &mut egui_contexts, &background),
-        generate_element_image(Element::Seed, &mut egui_contexts, &background),
-        generate_element_image(Element::Plant, &mut egui_contexts, &background),
-        generate_element_image(Element::TNT, &mut egui_contexts, &background),
-        generate_element_image(Element::Fuse, &mut egui_contexts, &background),
-        generate_element_image(Element::Explosion, &mut egui_contexts, &background),
-        generate_element_image(Element::WaterSource, &mut egui_contexts, &background),
-        generate_element_image(Element::AcidSource, &mut egui_contexts, &background),
-        generate_element_image(Element::OilSource, &mut egui_contexts, &background),
-        generate_element_image(Element::FireSource, &mut egui_contexts, &background),
-        generate_element_image(Element::LavaSource, &mut egui_contexts, &background),
-        generate_element_image(Element::Indestructible, &mut egui_contexts, &background),
-    ];
+    let element_icons = generate_element_icons(egui_contexts.ctx_mut(), settings.theme);
 
     let element_names = element_names(settings.language);
 
     commands.insert_resource(SandboxGui {
         mode: GuiMode::MainGui,
+        show_statistics: false,
+        show_debug: false,
         last_element: Element::Sand,
         bucket_icon_handle: add_icon(
             &mut egui_contexts,
@@ -552,6 +797,11 @@ fn setup_gui(mut commands: Commands, mut egui_contexts: EguiContexts, settings:
             "icon_zoom_out",
             include_bytes!("../assets/icon_zoom_out.png"),
         ),
+        icon_fit_handle: add_icon(
+            &mut egui_contexts,
+            "icon_fit",
+            include_bytes!("../assets/icon_fit.png"),
+        ),
         icon_move_handle: add_icon(
             &mut egui_contexts,
             "icon_move",
@@ -634,11 +884,24 @@ fn element_button(ui: &mut Ui, gui: &mut SandboxGui, element: Element) -> Respon
     response
 }
 
+// Icons of all elements in the given theme
+fn generate_element_icons(
+    egui_context: &egui::Context,
+    theme: Theme,
+) -> [TextureHandle; ELEMENT_COUNT] {
+    let background = image::load_from_memory(include_bytes!("../assets/icon_element.png")).unwrap();
+    std::array::from_fn(|value| {
+        let element = Element::try_from(value as u8).unwrap();
+        generate_element_image(element, egui_context, &background, theme)
+    })
+}
+
 // Create a button image for element selection
 pub fn generate_element_image(
     element: Element,
-    egui_context: &mut EguiContexts,
+    egui_context: &egui::Context,
     background: &DynamicImage,
+    theme: Theme,
 ) -> TextureHandle {
     // Generate a tiny sandbox containing our element
     let size = 64;
@@ -660,8 +923,8 @@ pub fn generate_element_image(
             let (or, og, ob, oa) = (pixel.0[0], pixel.0[1], pixel.0[2], pixel.0[3]);
 
             // Get the element color
-            let cell = sandbox.get_mut(x, y);
-            let (cr, cg, cb) = cell_color(cell, &mut random);
+            let cell = sandbox.get(x, y);
+            let (cr, cg, cb) = cell_color(cell, x, y, theme, &mut random);
 
             // Do a simplified alpha blend between the two to soften the edges
             let dx = (center - x as isize).abs() as f32;
@@ -674,7 +937,7 @@ pub fn generate_element_image(
         }
     }
 
-    egui_context.ctx_mut().load_texture(
+    egui_context.load_texture(
         format!("element_{}", element as u8),
         img,
         Default::default(),
@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{network::NetworkClient, sandbox::SandBox, simulation::Simulation};
+
+// Number of simulation steps between two snapshots
+const SNAPSHOT_INTERVAL: u64 = 10;
+// Oldest snapshots are dropped when the history grows beyond this size
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
+// Compressed snapshots of the sandbox taken while simulating, used to rewind time
+#[derive(Resource, Default)]
+pub struct History {
+    snapshots: VecDeque<Vec<u8>>,
+    memory_bytes: usize,
+    size: (usize, usize),
+    last_snapshot_step: u64,
+    // Snapshot currently shown, or None when at the latest simulation step
+    position: Option<usize>,
+}
+
+impl History {
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    // Index of the snapshot currently shown, the latest one when not rewound
+    pub fn position(&self) -> usize {
+        self.position
+            .unwrap_or_else(|| self.snapshots.len().saturating_sub(1))
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.memory_bytes = 0;
+        self.position = None;
+    }
+
+    fn push(&mut self, sandbox: &SandBox, step: u64) {
+        let size = (sandbox.width(), sandbox.height());
+        if size != self.size {
+            self.clear();
+            self.size = size;
+        }
+        let snapshot = sandbox.to_bytes();
+        self.memory_bytes += snapshot.len();
+        self.snapshots.push_back(snapshot);
+        while self.memory_bytes > MAX_MEMORY_BYTES && self.snapshots.len() > 1 {
+            let oldest = self.snapshots.pop_front().unwrap();
+            self.memory_bytes -= oldest.len();
+        }
+        self.last_snapshot_step = step;
+    }
+
+    // Show an earlier snapshot in the sandbox. Simulating from there discards the snapshots after it.
+    pub fn restore(&mut self, index: usize, sandbox: &mut SandBox, simulation: &mut Simulation) {
+        let Some(snapshot) = self.snapshots.get(index) else {
+            return;
+        };
+        if let Some(restored) = SandBox::from_bytes_with_border(snapshot) {
+            *sandbox = restored;
+            self.position = Some(index);
+            self.last_snapshot_step = simulation.step_count;
+            simulation.running = false;
+        }
+    }
+
+    // Continue from the snapshot currently shown, forgetting what came after it
+    fn resume(&mut self) {
+        if let Some(position) = self.position.take() {
+            while self.snapshots.len() > position + 1 {
+                let newest = self.snapshots.pop_back().unwrap();
+                self.memory_bytes -= newest.len();
+            }
+        }
+    }
+}
+
+// System that takes a snapshot of the sandbox every few simulation steps
+pub fn history_system(
+    mut history: ResMut<History>,
+    simulation: Res<Simulation>,
+    sandbox: Query<&SandBox>,
+    new_sandbox: Query<(), Added<SandBox>>,
+    client: Option<Res<NetworkClient>>,
+) {
+    let Ok(sandbox) = sandbox.get_single() else {
+        return;
+    };
+    if client.is_some() {
+        // Restoring a snapshot would only change the sandbox shown, not the one of the server
+        history.clear();
+        return;
+    }
+    if !new_sandbox.is_empty() || simulation.step_count < history.last_snapshot_step {
+        // A different sandbox or the simulation started over, the snapshots belong to another world
+        history.clear();
+        history.last_snapshot_step = simulation.step_count;
+    }
+    if simulation.step_count == history.last_snapshot_step {
+        return;
+    }
+    if history.position.is_some() {
+        // The simulation continued from a restored snapshot
+        history.resume();
+    }
+    if history.is_empty() || simulation.step_count - history.last_snapshot_step >= SNAPSHOT_INTERVAL
+    {
+        history.push(sandbox, simulation.step_count);
+    }
+}
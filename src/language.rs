@@ -1,100 +1,124 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, str::FromStr, sync::OnceLock};
 
-use crate::element::Element;
+use crate::element::{Element, ELEMENT_COUNT};
 
-// Simplistic translation system to let my kids play more easily
+// Language files from assets/lang, embedded by the build script
+include!(concat!(env!("OUT_DIR"), "/languages.rs"));
+
+// Language used when no better match is available, and for texts missing in other languages
+const FALLBACK_CODE: &str = "en";
+
+// Simplistic translation system to let my kids play more easily.
+// Each language is a file of "key = value" lines named after its language code.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub enum Language {
-    English,
-    Nederlands,
+pub struct Language(usize);
+
+struct LanguageTexts {
+    code: &'static str,
+    texts: HashMap<&'static str, &'static str>,
 }
 
-pub fn element_names(language: Language) -> HashMap<Element, String> {
-    let mut names = HashMap::new();
+fn languages() -> &'static [LanguageTexts] {
+    static LANGUAGES: OnceLock<Vec<LanguageTexts>> = OnceLock::new();
+    LANGUAGES.get_or_init(|| {
+        LANGUAGE_FILES
+            .iter()
+            .map(|(code, contents)| LanguageTexts {
+                code,
+                texts: parse_language_file(contents),
+            })
+            .collect()
+    })
+}
+
+fn parse_language_file(contents: &'static str) -> HashMap<&'static str, &'static str> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .collect()
+}
+
+impl Language {
+    pub fn all() -> impl Iterator<Item = Language> {
+        (0..languages().len()).map(Language)
+    }
 
-    match language {
-        Language::English => set_english_element_names(&mut names),
-        Language::Nederlands => set_dutch_element_names(&mut names),
+    pub fn fallback() -> Language {
+        FALLBACK_CODE
+            .parse()
+            .expect("fallback language file is missing")
     }
 
-    names
+    // Pick the language matching the locale of the OS or browser
+    pub fn detect() -> Language {
+        sys_locale::get_locale()
+            .and_then(|locale| {
+                let code = locale.split(['-', '_', '.']).next()?.to_lowercase();
+                code.parse().ok()
+            })
+            .unwrap_or_else(Language::fallback)
+    }
+
+    pub fn code(&self) -> &'static str {
+        languages()[self.0].code
+    }
+
+    pub fn name(&self) -> &'static str {
+        get_text("name", *self)
+    }
+
+    // Keys that are defined in the fallback language but not in this one
+    pub fn missing_keys(&self) -> Vec<&'static str> {
+        let texts = &languages()[self.0].texts;
+        let mut missing: Vec<_> = languages()[Language::fallback().0]
+            .texts
+            .keys()
+            .filter(|key| !texts.contains_key(*key))
+            .copied()
+            .collect();
+        missing.sort();
+        missing
+    }
 }
 
-fn set_english_element_names(names: &mut HashMap<Element, String>) {
-    names.insert(Element::Air, "Air".to_string());
-    names.insert(Element::Acid, "Acid".to_string());
-    names.insert(Element::AcidSource, "Acid source".to_string());
-    names.insert(Element::Ash, "Ash".to_string());
-    names.insert(Element::Drain, "Drain".to_string());
-    names.insert(Element::Explosion, "Explision".to_string());
-    names.insert(Element::Fire, "Fire".to_string());
-    names.insert(Element::FireSource, "Fire source".to_string());
-    names.insert(Element::Fuse, "Fuse".to_string());
-    names.insert(Element::Indestructible, "Indestructible".to_string());
-    names.insert(Element::Iron, "Iron".to_string());
-    names.insert(Element::Lava, "Lava".to_string());
-    names.insert(Element::LavaSource, "Lava source".to_string());
-    names.insert(Element::Life, "Life".to_string());
-    names.insert(Element::Oil, "Oil".to_string());
-    names.insert(Element::OilSource, "Oil source".to_string());
-    names.insert(Element::Plant, "Plant".to_string());
-    names.insert(Element::Rock, "Stone".to_string());
-    names.insert(Element::Rust, "Rust".to_string());
-    names.insert(Element::Sand, "Sand".to_string());
-    names.insert(Element::Seed, "Seed".to_string());
-    names.insert(Element::Smoke, "Smoke".to_string());
-    names.insert(Element::TNT, "TNT".to_string());
-    names.insert(Element::Water, "Water".to_string());
-    names.insert(Element::WaterSource, "Water source".to_string());
-    names.insert(Element::Wood, "Wood".to_string());
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
 }
 
-fn set_dutch_element_names(names: &mut HashMap<Element, String>) {
-    names.insert(Element::Air, "Luucht".to_string());
-    names.insert(Element::Acid, "Zuur".to_string());
-    names.insert(Element::AcidSource, "Zuur bron".to_string());
-    names.insert(Element::Ash, "As".to_string());
-    names.insert(Element::Drain, "Afvoer".to_string());
-    names.insert(Element::Explosion, "Explosie".to_string());
-    names.insert(Element::Fire, "Vuur".to_string());
-    names.insert(Element::FireSource, "Vuuur bron".to_string());
-    names.insert(Element::Fuse, "Lont".to_string());
-    names.insert(Element::Indestructible, "Onkwetsbaar".to_string());
-    names.insert(Element::Iron, "IJzer".to_string());
-    names.insert(Element::Lava, "Lava".to_string());
-    names.insert(Element::LavaSource, "Lava bron".to_string());
-    names.insert(Element::Life, "Leven".to_string());
-    names.insert(Element::Oil, "Olie".to_string());
-    names.insert(Element::OilSource, "Olie bron".to_string());
-    names.insert(Element::Plant, "Plant".to_string());
-    names.insert(Element::Rock, "Steen".to_string());
-    names.insert(Element::Rust, "Roest".to_string());
-    names.insert(Element::Sand, "Zand".to_string());
-    names.insert(Element::Seed, "Zaad".to_string());
-    names.insert(Element::Smoke, "Rook".to_string());
-    names.insert(Element::TNT, "TNT".to_string());
-    names.insert(Element::Water, "Water".to_string());
-    names.insert(Element::WaterSource, "Water bron".to_string());
-    names.insert(Element::Wood, "Hout".to_string());
+impl FromStr for Language {
+    type Err = ();
+
+    // Parse a language from its code, e.g. "en"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        languages()
+            .iter()
+            .position(|language| language.code == s)
+            .map(Language)
+            .ok_or(())
+    }
 }
 
+pub fn element_names(language: Language) -> HashMap<Element, String> {
+    (0..ELEMENT_COUNT as u8)
+        .filter_map(|value| Element::try_from(value).ok())
+        .map(|element| {
+            let key = format!("element.{}", element);
+            (element, get_text(&key, language).to_string())
+        })
+        .collect()
+}
+
+// Get a text in the given language, falling back to English and finally the key itself
 pub fn get_text(code: &str, language: Language) -> &str {
-    match language {
-        Language::English => match code {
-            "language" => "Language",
-            "simulation" => "Simulation",
-            "render" => "Render",
-            "new" => "New",
-            "size" => "Size",
-            _ => "UNKNOWN",
-        },
-        Language::Nederlands => match code {
-            "language" => "Taal",
-            "simulation" => "Simulatie",
-            "render" => "Weergave",
-            "new" => "Nieuw",
-            "size" => "Afmeting",
-            _ => "ONBEKEND",
-        },
-    }
+    languages()[language.0]
+        .texts
+        .get(code)
+        .or_else(|| languages()[Language::fallback().0].texts.get(code))
+        .copied()
+        .unwrap_or(code)
 }
@@ -1,26 +1,53 @@
-mod cell;
+mod brush_preview;
+mod camera;
+pub mod cell;
+pub mod conservation;
 pub mod element;
 mod fill_browser;
+mod gamepad;
+mod gpu_render;
 mod gui;
-mod language;
+mod history;
+pub mod language;
+mod lighting;
+pub mod lockstep;
+pub mod neighbourhood;
+pub mod network;
+mod persistence;
 mod pointer_input;
-mod pseudo_random;
-mod render;
+pub mod pseudo_random;
+pub mod render;
 pub mod sandbox;
+pub mod scripting;
 mod settings;
 pub mod simulation;
-mod toolbox;
+mod statistics;
+pub mod theme;
+pub mod toolbox;
+pub mod touch;
+pub mod world;
 
 use bevy::{prelude::*, window::WindowResolution};
+use brush_preview::BrushPreviewPlugin;
+use camera::CameraPlugin;
 use fill_browser::*;
+use gamepad::GamepadPlugin;
+use gpu_render::GpuRenderPlugin;
 use gui::GuiPlugin;
+use history::{history_system, History};
+use lighting::LightingPlugin;
+use lockstep::LockstepPlugin;
+use network::NetworkPlugin;
+use persistence::PersistencePlugin;
 use pointer_input::PointerInputPlugin;
 use pseudo_random::PseudoRandom;
-use render::{render_system, RenderState};
+use render::{render_system, DebugView, RenderState};
 use sandbox::*;
+use scripting::{script_drives_simulation, ScriptPlugin};
 use settings::Settings;
 use simulation::{simulation_system, Simulation};
-use toolbox::ToolBox;
+use statistics::{statistics_system, Statistics};
+use world::{ChunkWorld, ChunkWorldPlugin};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemSet)]
 pub enum SystemOrderLabel {
@@ -43,22 +70,49 @@ pub fn start_app() {
                 .set(ImagePlugin::default_nearest()),
         )
         .add_plugin(FillBrowserWindowPlugin)
+        .add_plugin(CameraPlugin)
+        .add_plugin(PersistencePlugin)
         .add_plugin(GuiPlugin)
         .add_plugin(PointerInputPlugin)
+        .add_plugin(GamepadPlugin)
+        .add_plugin(BrushPreviewPlugin)
+        .add_plugin(ScriptPlugin)
+        .add_plugin(LightingPlugin)
+        .add_plugin(GpuRenderPlugin)
+        .add_plugin(NetworkPlugin)
+        .add_plugin(LockstepPlugin)
+        .add_plugin(ChunkWorldPlugin)
         .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
         .init_resource::<Simulation>()
-        .init_resource::<ToolBox>()
-        .init_resource::<Settings>()
+        .init_resource::<History>()
+        .init_resource::<Statistics>()
         .insert_resource(RenderState {
             random: PseudoRandom::new(),
+            debug_view: DebugView::None,
         })
         .add_startup_system(setup)
-        .add_system(simulation_system)
+        .add_system(simulation_system.run_if(not(script_drives_simulation)))
+        .add_system(history_system.after(simulation_system))
+        .add_system(statistics_system.after(simulation_system))
         .add_system(render_system)
         .run();
 }
 
-fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+fn setup(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    settings: Res<Settings>,
+    world: Option<Res<ChunkWorld>>,
+) {
     commands.spawn(Camera2dBundle::default());
-    spawn_sandbox(commands, images.as_mut(), 256, 256);
+    if world.is_some() {
+        // The world takes the place of the sandbox
+        return;
+    }
+    spawn_sandbox(
+        commands,
+        images.as_mut(),
+        settings.sandbox_size,
+        settings.sandbox_size,
+    );
 }
@@ -0,0 +1,222 @@
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::ImageSampler,
+    },
+};
+
+use crate::{
+    element::{element_type, Element, ElementForm},
+    render::render_system,
+    sandbox::SandBox,
+    settings::Settings,
+};
+
+// Size in cells of the square blocks the light is computed for, must match the cell shader
+pub const LIGHT_SCALE: usize = 4;
+// Light level of cells the sky light does not reach
+const AMBIENT_LIGHT: f32 = 0.2;
+// Part of the sky light blocked by a block full of non-gas cells
+const SKY_ABSORPTION: f32 = 0.35;
+// Strength of the light given off by glowing cells
+const GLOW_STRENGTH: f32 = 0.5;
+// Strength of the glow added on top of the lit colors
+const BLOOM: f32 = 0.4;
+
+// Texture with the glow color in RGB and the sky light in alpha for each block, used by the cell shader
+pub const LIGHT_MAP_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Image::TYPE_UUID, 4471036260719182643);
+
+/// Lights the sandbox with glowing elements and sky light that does not reach underground
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app.world
+            .resource_mut::<Assets<Image>>()
+            .set_untracked(LIGHT_MAP_HANDLE, light_image(1, 1, vec![0, 0, 0, 255]));
+        app.init_resource::<LightMap>()
+            .add_system(lighting_system.before(render_system));
+    }
+}
+
+// Light for each block of cells, empty when lighting is off
+#[derive(Resource, Default)]
+pub struct LightMap {
+    width: usize,
+    height: usize,
+    // Light given off by glowing cells, per color channel
+    glow: Vec<[f32; 3]>,
+    // Part of the sky light reaching the block
+    sky: Vec<f32>,
+}
+
+impl LightMap {
+    pub fn is_empty(&self) -> bool {
+        self.glow.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.width = 0;
+        self.height = 0;
+        self.glow.clear();
+        self.sky.clear();
+    }
+
+    fn update(&mut self, sandbox: &SandBox) {
+        self.width = sandbox.width().div_ceil(LIGHT_SCALE);
+        self.height = sandbox.height().div_ceil(LIGHT_SCALE);
+        let blocks = self.width * self.height;
+        self.glow.clear();
+        self.glow.resize(blocks, [0.0; 3]);
+        self.sky.clear();
+        self.sky.resize(blocks, 0.0);
+
+        // Gather how much each block blocks the sky, and the light given off in it
+        let cells_per_block = (LIGHT_SCALE * LIGHT_SCALE) as f32;
+        let mut opacity = vec![0.0; blocks];
+        let mut emission = vec![([0.0; 3], 0u8); blocks];
+        for y in 0..sandbox.height() {
+            for x in 0..sandbox.width() {
+                let element = sandbox.get(x, y).element;
+                let element_type = element_type(element);
+                let block = x / LIGHT_SCALE + (y / LIGHT_SCALE) * self.width;
+                // The border does not count, or the sides would be dark
+                if element_type.form != ElementForm::Gas && element != Element::Indestructible {
+                    opacity[block] += 1.0 / cells_per_block;
+                }
+                if element_type.light_radius > 0 {
+                    let (color, radius) = &mut emission[block];
+                    let (r, g, b) = element_type.light;
+                    color[0] += r as f32 / 255.0 / cells_per_block;
+                    color[1] += g as f32 / 255.0 / cells_per_block;
+                    color[2] += b as f32 / 255.0 / cells_per_block;
+                    *radius = (*radius).max(element_type.light_radius);
+                }
+            }
+        }
+
+        // Sky light shines down from the top, dimmed by everything it passes
+        for x in 0..self.width {
+            let mut sky = 1.0;
+            for y in 0..self.height {
+                let block = x + y * self.width;
+                self.sky[block] = sky;
+                sky *= 1.0 - SKY_ABSORPTION * opacity[block];
+            }
+        }
+
+        // Spread the light of glowing blocks around them, fading with the distance
+        for (block, (color, radius)) in emission.iter().enumerate() {
+            if *radius == 0 {
+                continue;
+            }
+            let (bx, by) = ((block % self.width) as isize, (block / self.width) as isize);
+            let reach = *radius as f32 / LIGHT_SCALE as f32;
+            let blocks = reach.ceil() as isize;
+            for dy in -blocks..=blocks {
+                for dx in -blocks..=blocks {
+                    let (x, y) = (bx + dx, by + dy);
+                    if x < 0 || y < 0 || x >= self.width as isize || y >= self.height as isize {
+                        continue;
+                    }
+                    let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                    if distance > reach {
+                        continue;
+                    }
+                    let falloff = (1.0 - distance / reach).powi(2) * GLOW_STRENGTH;
+                    let glow = &mut self.glow[x as usize + y as usize * self.width];
+                    for channel in 0..3 {
+                        glow[channel] += color[channel] * falloff;
+                    }
+                }
+            }
+        }
+    }
+
+    // Glow and sky light at a cell, smoothly interpolated between the blocks
+    pub fn light(&self, x: usize, y: usize) -> ([f32; 3], f32) {
+        let fx = ((x as f32 + 0.5) / LIGHT_SCALE as f32 - 0.5).max(0.0);
+        let fy = ((y as f32 + 0.5) / LIGHT_SCALE as f32 - 0.5).max(0.0);
+        let (x0, y0) = (fx as usize, fy as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+        let weights = [
+            (x0 + y0 * self.width, (1.0 - tx) * (1.0 - ty)),
+            (x1 + y0 * self.width, tx * (1.0 - ty)),
+            (x0 + y1 * self.width, (1.0 - tx) * ty),
+            (x1 + y1 * self.width, tx * ty),
+        ];
+        let mut glow = [0.0; 3];
+        let mut sky = 0.0;
+        for (block, weight) in weights {
+            for (glow, block_glow) in glow.iter_mut().zip(self.glow[block]) {
+                *glow += block_glow.min(1.0) * weight;
+            }
+            sky += self.sky[block] * weight;
+        }
+        (glow, sky)
+    }
+
+    fn to_image(&self) -> Image {
+        let mut data = Vec::with_capacity(self.glow.len() * 4);
+        for (glow, sky) in self.glow.iter().zip(self.sky.iter()) {
+            data.extend_from_slice(&[
+                (glow[0].min(1.0) * 255.0) as u8,
+                (glow[1].min(1.0) * 255.0) as u8,
+                (glow[2].min(1.0) * 255.0) as u8,
+                (sky * 255.0) as u8,
+            ]);
+        }
+        light_image(self.width as u32, self.height as u32, data)
+    }
+}
+
+// Light up a color, the same way as the cell shader does
+pub fn apply_light(color: (u8, u8, u8), glow: [f32; 3], sky: f32) -> (u8, u8, u8) {
+    let channel = |value: u8, glow: f32| {
+        let light = (AMBIENT_LIGHT + sky * (1.0 - AMBIENT_LIGHT) + glow).min(1.0);
+        (value as f32 * light + glow * BLOOM * 255.0).min(255.0) as u8
+    };
+    (
+        channel(color.0, glow[0]),
+        channel(color.1, glow[1]),
+        channel(color.2, glow[2]),
+    )
+}
+
+fn light_image(width: u32, height: u32, data: Vec<u8>) -> Image {
+    let mut image = Image::new(
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8Unorm,
+    );
+    image.sampler_descriptor = ImageSampler::linear();
+    image
+}
+
+// System computing the light of the sandbox each frame, when enabled
+fn lighting_system(
+    settings: Res<Settings>,
+    mut light_map: ResMut<LightMap>,
+    mut images: ResMut<Assets<Image>>,
+    sandbox: Query<&SandBox>,
+) {
+    let Ok(sandbox) = sandbox.get_single() else {
+        return;
+    };
+    if settings.lighting {
+        light_map.update(sandbox);
+        images.set_untracked(LIGHT_MAP_HANDLE, light_map.to_image());
+    } else if !light_map.is_empty() {
+        light_map.clear();
+        images.set_untracked(LIGHT_MAP_HANDLE, light_image(1, 1, vec![0, 0, 0, 255]));
+    }
+}
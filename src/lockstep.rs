@@ -0,0 +1,493 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    io::{self, ErrorKind},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    network::{Connection, RemoteTools, ToolApplication, APPLICATION_BYTES, DEFAULT_PORT},
+    sandbox::{spawn_existing_sandbox, SandBox, MAX_SANDBOX_SIZE},
+    simulation::{simulation_step, simulation_system, Simulation},
+    toolbox::ToolBox,
+};
+
+// Frames between using a tool and applying it, so the inputs can reach all peers in time
+pub const INPUT_DELAY: u64 = 4;
+// Frames between comparing the state with the other peers
+pub const HASH_INTERVAL: u64 = 30;
+// Own hashes are kept this many frames, waiting for those of slower peers
+const HASH_HISTORY: u64 = HASH_INTERVAL * 20;
+// Other peers wait for the inputs of this one, so their messages are never for frames further
+// ahead than this. Later frames are dropped instead of kept without limit.
+const MAX_FRAMES_AHEAD: u64 = INPUT_DELAY * 2;
+
+// Message kinds, next to those of the client and server
+const WELCOME: u8 = 10;
+const INPUTS: u8 = 11;
+const HASH: u8 = 12;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LockstepMessage {
+    // Tools a player used, to apply before simulating the frame
+    Inputs {
+        player: usize,
+        frame: u64,
+        applications: Vec<ToolApplication>,
+    },
+    // Hash of the state of a player after simulating up to the frame
+    Hash {
+        player: usize,
+        frame: u64,
+        hash: u64,
+    },
+}
+
+impl LockstepMessage {
+    fn player(&self) -> usize {
+        match self {
+            LockstepMessage::Inputs { player, .. } | LockstepMessage::Hash { player, .. } => {
+                *player
+            }
+        }
+    }
+
+    fn encode(&self) -> (u8, Vec<u8>) {
+        match self {
+            LockstepMessage::Inputs {
+                player,
+                frame,
+                applications,
+            } => {
+                let mut bytes = vec![*player as u8];
+                bytes.extend_from_slice(&frame.to_le_bytes());
+                for application in applications {
+                    bytes.extend_from_slice(&application.to_bytes());
+                }
+                (INPUTS, bytes)
+            }
+            LockstepMessage::Hash {
+                player,
+                frame,
+                hash,
+            } => {
+                let mut bytes = vec![*player as u8];
+                bytes.extend_from_slice(&frame.to_le_bytes());
+                bytes.extend_from_slice(&hash.to_le_bytes());
+                (HASH, bytes)
+            }
+        }
+    }
+
+    fn decode(kind: u8, bytes: &[u8]) -> Option<LockstepMessage> {
+        if bytes.len() < 9 {
+            return None;
+        }
+        let player = bytes[0] as usize;
+        let frame = u64::from_le_bytes(bytes[1..9].try_into().unwrap());
+        match kind {
+            INPUTS if (bytes.len() - 9).is_multiple_of(APPLICATION_BYTES) => {
+                let applications = bytes[9..]
+                    .chunks(APPLICATION_BYTES)
+                    .map(ToolApplication::from_bytes)
+                    .collect::<Option<Vec<_>>>()?;
+                Some(LockstepMessage::Inputs {
+                    player,
+                    frame,
+                    applications,
+                })
+            }
+            HASH if bytes.len() == 17 => Some(LockstepMessage::Hash {
+                player,
+                frame,
+                hash: u64::from_le_bytes(bytes[9..17].try_into().unwrap()),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// Simulation that only advances a frame when the inputs of all players for it are known, so
+// every peer applies the same inputs in the same order and stays in sync
+pub struct Lockstep {
+    player: usize,
+    players: usize,
+    // Next frame to simulate
+    frame: u64,
+    // Inputs of each player for the frames still to simulate
+    inputs: BTreeMap<u64, Vec<Option<Vec<ToolApplication>>>>,
+    // Tools used locally since the last frame
+    local: Vec<ToolApplication>,
+    // Tools of each player, which all start the same
+    toolboxes: Vec<ToolBox>,
+    hashes: BTreeMap<u64, u64>,
+    // Hashes of other players for frames not yet simulated here, by frame and player
+    remote_hashes: BTreeMap<(u64, usize), u64>,
+    desync: Option<u64>,
+}
+
+impl Lockstep {
+    pub fn new(player: usize, players: usize) -> Self {
+        let mut inputs = BTreeMap::new();
+        // Nobody can have used a tool for the first frames
+        for frame in 0..INPUT_DELAY {
+            inputs.insert(frame, vec![Some(Vec::new()); players]);
+        }
+        Lockstep {
+            player,
+            players,
+            frame: 0,
+            inputs,
+            local: Vec::new(),
+            toolboxes: (0..players).map(|_| ToolBox::default()).collect(),
+            hashes: BTreeMap::new(),
+            remote_hashes: BTreeMap::new(),
+            desync: None,
+        }
+    }
+
+    pub fn player(&self) -> usize {
+        self.player
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // First frame at which the state differed from that of another player
+    pub fn desync(&self) -> Option<u64> {
+        self.desync
+    }
+
+    // Use a tool, it is applied by all peers a few frames later
+    pub fn queue(&mut self, application: ToolApplication) {
+        self.local.push(application);
+    }
+
+    // Whether the inputs of all players for the next frame are known
+    pub fn ready(&self) -> bool {
+        self.inputs
+            .get(&self.frame)
+            .is_some_and(|inputs| inputs.iter().all(Option::is_some))
+    }
+
+    pub fn receive(&mut self, message: LockstepMessage) {
+        if message.player() >= self.players {
+            return;
+        }
+        match message {
+            LockstepMessage::Inputs {
+                player,
+                frame,
+                applications,
+            } => {
+                if (self.frame..self.frame + MAX_FRAMES_AHEAD).contains(&frame) {
+                    self.inputs
+                        .entry(frame)
+                        .or_insert_with(|| vec![None; self.players])[player] = Some(applications);
+                }
+            }
+            LockstepMessage::Hash {
+                player,
+                frame,
+                hash,
+            } => match self.hashes.get(&frame) {
+                Some(own) => self.compare(frame, *own, hash),
+                None if (self.frame..self.frame + MAX_FRAMES_AHEAD).contains(&frame) => {
+                    self.remote_hashes.insert((frame, player), hash);
+                }
+                None => {}
+            },
+        }
+    }
+
+    fn compare(&mut self, frame: u64, own: u64, other: u64) {
+        if own != other && self.desync.is_none_or(|desync| frame < desync) {
+            self.desync = Some(frame);
+        }
+    }
+
+    // Simulate the next frame if possible, returns the messages for the other peers
+    pub fn step(
+        &mut self,
+        simulation: &mut Simulation,
+        sandbox: &mut SandBox,
+    ) -> Vec<LockstepMessage> {
+        if !self.ready() {
+            return Vec::new();
+        }
+        let mut messages = Vec::new();
+
+        // Send the local inputs for a later frame, so they arrive before they are needed
+        let applications: Vec<ToolApplication> = self.local.drain(..).collect();
+        let input_frame = self.frame + INPUT_DELAY;
+        self.inputs
+            .entry(input_frame)
+            .or_insert_with(|| vec![None; self.players])[self.player] = Some(applications.clone());
+        messages.push(LockstepMessage::Inputs {
+            player: self.player,
+            frame: input_frame,
+            applications,
+        });
+
+        // Apply everyone's inputs in the order of the players
+        let inputs = self.inputs.remove(&self.frame).unwrap_or_default();
+        for (toolbox, applications) in self.toolboxes.iter_mut().zip(inputs) {
+            for application in applications.unwrap_or_default() {
+                if application.x >= sandbox.width() || application.y >= sandbox.height() {
+                    continue;
+                }
+                toolbox.tool = application.tool;
+                toolbox.element = application.element;
+                toolbox.tool_size = application.tool_size;
+                toolbox.apply(sandbox, application.x, application.y);
+            }
+        }
+        simulation.step = true;
+        simulation_step(simulation, sandbox);
+        self.frame += 1;
+
+        if self.frame.is_multiple_of(HASH_INTERVAL) {
+            let hash = simulation.state_hash(sandbox);
+            self.hashes.insert(self.frame, hash);
+            let frame = self.frame;
+            let remote: Vec<u64> = self
+                .remote_hashes
+                .range((frame, 0)..(frame + 1, 0))
+                .map(|(_, remote_hash)| *remote_hash)
+                .collect();
+            for remote_hash in remote {
+                self.compare(frame, hash, remote_hash);
+            }
+            self.remote_hashes
+                .retain(|(remote_frame, _), _| *remote_frame > frame);
+            self.hashes
+                .retain(|hash_frame, _| hash_frame + HASH_HISTORY > frame);
+            messages.push(LockstepMessage::Hash {
+                player: self.player,
+                frame,
+                hash,
+            });
+        }
+        messages
+    }
+}
+
+// A lockstep session over TCP. The host waits for all players to join, and passes on the
+// messages of each player to the others.
+pub struct LockstepPeer {
+    listener: Option<TcpListener>,
+    connections: Vec<Connection>,
+    players: usize,
+    pub lockstep: Option<Lockstep>,
+}
+
+impl LockstepPeer {
+    pub fn host(address: impl ToSocketAddrs, players: usize) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(LockstepPeer {
+            listener: Some(listener),
+            connections: Vec::new(),
+            players: players.clamp(1, 255),
+            lockstep: None,
+        })
+    }
+
+    pub fn join(address: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(LockstepPeer {
+            listener: None,
+            connections: vec![Connection::new(TcpStream::connect(address)?)?],
+            players: 0,
+            lockstep: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener
+            .as_ref()
+            .and_then(|listener| listener.local_addr().ok())
+    }
+
+    // Accept players and exchange messages. Returns the size of the sandbox when the session
+    // starts, all players have to start from the same empty sandbox.
+    pub fn poll(&mut self, sandbox_size: (usize, usize)) -> io::Result<Option<(usize, usize)>> {
+        let mut started = None;
+        if let Some(listener) = &self.listener {
+            while self.connections.len() + 1 < self.players {
+                match listener.accept() {
+                    Ok((stream, _)) => self.connections.push(Connection::new(stream)?),
+                    Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                    Err(error) => return Err(error),
+                }
+            }
+            if self.connections.len() + 1 == self.players {
+                let (width, height) = sandbox_size;
+                for (index, connection) in self.connections.iter_mut().enumerate() {
+                    let mut welcome = vec![index as u8 + 1, self.players as u8];
+                    welcome.extend_from_slice(&(width as u32).to_le_bytes());
+                    welcome.extend_from_slice(&(height as u32).to_le_bytes());
+                    connection.send(WELCOME, &welcome);
+                }
+                self.listener = None;
+                self.lockstep = Some(Lockstep::new(0, self.players));
+                started = Some(sandbox_size);
+            }
+        }
+
+        let host = self.players > 0;
+        for index in 0..self.connections.len() {
+            for (kind, payload) in self.connections[index].receive()? {
+                if kind == WELCOME && !host && payload.len() == 10 {
+                    let (player, players) = (payload[0] as usize, payload[1] as usize);
+                    let width = u32::from_le_bytes(payload[2..6].try_into().unwrap()) as usize;
+                    let height = u32::from_le_bytes(payload[6..10].try_into().unwrap()) as usize;
+                    // Refuse sessions without a place for this player or sandbox to play in
+                    let sizes = 3..=MAX_SANDBOX_SIZE;
+                    if player >= players || !sizes.contains(&width) || !sizes.contains(&height) {
+                        return Err(ErrorKind::InvalidData.into());
+                    }
+                    self.lockstep = Some(Lockstep::new(player, players));
+                    started = Some((width, height));
+                    continue;
+                }
+                let message =
+                    LockstepMessage::decode(kind, &payload).ok_or(ErrorKind::InvalidData)?;
+                if host {
+                    // Guests only speak for themselves, the host is player 0
+                    if message.player() != index + 1 {
+                        return Err(ErrorKind::InvalidData.into());
+                    }
+                    for (other, connection) in self.connections.iter_mut().enumerate() {
+                        if other != index {
+                            connection.send(kind, &payload);
+                        }
+                    }
+                }
+                if let Some(lockstep) = self.lockstep.as_mut() {
+                    lockstep.receive(message);
+                }
+            }
+        }
+        for connection in self.connections.iter_mut() {
+            connection.flush()?;
+        }
+        Ok(started)
+    }
+
+    pub fn send(&mut self, messages: &[LockstepMessage]) -> io::Result<()> {
+        for message in messages {
+            let (kind, payload) = message.encode();
+            for connection in self.connections.iter_mut() {
+                connection.send(kind, &payload);
+            }
+        }
+        for connection in self.connections.iter_mut() {
+            connection.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Simulates in lockstep with other players, given with `--lockstep-host <players>` or
+/// `--lockstep-join <address>`
+pub struct LockstepPlugin;
+
+impl Plugin for LockstepPlugin {
+    fn build(&self, app: &mut App) {
+        let args: Vec<String> = env::args().collect();
+        let argument = |name: &str| {
+            args.iter()
+                .position(|arg| arg == name)
+                .and_then(|index| args.get(index + 1))
+        };
+        let peer = if let Some(players) = argument("--lockstep-host") {
+            let players = players.parse().unwrap_or(2);
+            info!("Waiting for {} players", players);
+            LockstepPeer::host(("0.0.0.0", DEFAULT_PORT), players)
+        } else if let Some(address) = argument("--lockstep-join") {
+            if address.contains(':') {
+                LockstepPeer::join(address)
+            } else {
+                LockstepPeer::join((address.as_str(), DEFAULT_PORT))
+            }
+        } else {
+            return;
+        };
+        match peer {
+            Ok(peer) => {
+                app.insert_resource(LockstepSession(peer))
+                    .init_resource::<RemoteTools>()
+                    .add_system(lockstep_system.before(simulation_system));
+            }
+            Err(error) => error!("Could not start the lockstep session: {}", error),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct LockstepSession(pub LockstepPeer);
+
+// System simulating a frame whenever the inputs of all players are known
+fn lockstep_system(
+    mut commands: Commands,
+    mut session: ResMut<LockstepSession>,
+    mut remote_tools: ResMut<RemoteTools>,
+    mut simulation: ResMut<Simulation>,
+    mut images: ResMut<Assets<Image>>,
+    mut sandbox: Query<(Entity, &mut SandBox)>,
+) {
+    // Only simulate in step with the other players
+    simulation.running = false;
+    simulation.step = false;
+    let Ok((entity, mut sandbox)) = sandbox.get_single_mut() else {
+        return;
+    };
+    let size = (sandbox.width(), sandbox.height());
+    let started = match session.0.poll(size) {
+        Ok(started) => started,
+        Err(error) => {
+            error!("Lockstep session ended: {}", error);
+            commands.remove_resource::<LockstepSession>();
+            commands.remove_resource::<RemoteTools>();
+            return;
+        }
+    };
+    if let Some((width, height)) = started {
+        // Everyone starts from the same state
+        let custom_update = simulation.custom_update.take();
+        *simulation = Simulation::new();
+        simulation.running = false;
+        simulation.custom_update = custom_update;
+        if (width, height) == size {
+            *sandbox = SandBox::new(width, height);
+        } else {
+            commands.entity(entity).despawn();
+            spawn_existing_sandbox(commands, images.as_mut(), SandBox::new(width, height));
+            return;
+        }
+    }
+
+    let Some(lockstep) = session.0.lockstep.as_mut() else {
+        // Still waiting for the other players
+        remote_tools.0.clear();
+        return;
+    };
+    for application in remote_tools.0.drain(..) {
+        lockstep.queue(application);
+    }
+    let desync = lockstep.desync();
+    let messages = lockstep.step(&mut simulation, &mut sandbox);
+    if desync.is_none() {
+        if let Some(frame) = lockstep.desync() {
+            error!("Out of sync with another player since frame {}", frame);
+        }
+    }
+    if let Err(error) = session.0.send(&messages) {
+        error!("Lockstep session ended: {}", error);
+        commands.remove_resource::<LockstepSession>();
+        commands.remove_resource::<RemoteTools>();
+    }
+}
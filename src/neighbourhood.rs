@@ -0,0 +1,106 @@
+use crate::{
+    cell::Cell,
+    element::{element_type, Element},
+    pseudo_random::PseudoRandom,
+    sandbox::SandBox,
+};
+
+// A copy of the 3x3 cells around a cell, given to custom element behaviour so it can
+// only touch its direct neighbours. Changes are written back to the sandbox afterwards.
+#[derive(Clone, Debug)]
+pub struct Neighbourhood {
+    cells: [Cell; 9],
+    changed: [bool; 9],
+    random: PseudoRandom,
+}
+
+impl Neighbourhood {
+    pub fn read(sandbox: &SandBox, x: usize, y: usize, random: u32) -> Self {
+        let cells = [
+            sandbox.get(x - 1, y - 1).clone(),
+            sandbox.get(x, y - 1).clone(),
+            sandbox.get(x + 1, y - 1).clone(),
+            sandbox.get(x - 1, y).clone(),
+            sandbox.get(x, y).clone(),
+            sandbox.get(x + 1, y).clone(),
+            sandbox.get(x - 1, y + 1).clone(),
+            sandbox.get(x, y + 1).clone(),
+            sandbox.get(x + 1, y + 1).clone(),
+        ];
+        Neighbourhood {
+            cells,
+            changed: [false; 9],
+            random: PseudoRandom::from_seed(random.max(1)),
+        }
+    }
+
+    // Write the changed cells back, returns true if the center cell was changed
+    pub fn write(&self, sandbox: &mut SandBox, x: usize, y: usize) -> bool {
+        for (index, cell) in self.cells.iter().enumerate() {
+            if self.changed[index] {
+                sandbox.set_cell(x + index % 3 - 1, y + index / 3 - 1, cell.clone());
+            }
+        }
+        self.changed[4]
+    }
+
+    // Cell at the given offset from the center, with offsets ranging from -1 to 1
+    pub fn get(&self, dx: isize, dy: isize) -> Option<&Cell> {
+        Self::index(dx, dy).map(|index| &self.cells[index])
+    }
+
+    pub fn set_element(&mut self, dx: isize, dy: isize, element: Element) -> bool {
+        let random = self.random.next();
+        self.modify(dx, dy, |cell| {
+            cell.element = element;
+            cell.strength = element_type(element).strength;
+            if element_type(element).render.uses_variant() {
+                cell.variant = random as u8;
+            }
+        })
+    }
+
+    pub fn set_strength(&mut self, dx: isize, dy: isize, strength: u8) -> bool {
+        self.modify(dx, dy, |cell| cell.strength = strength)
+    }
+
+    pub fn set_variant(&mut self, dx: isize, dy: isize, variant: u8) -> bool {
+        self.modify(dx, dy, |cell| cell.variant = variant)
+    }
+
+    // Swap the center cell with the cell at the given offset
+    pub fn swap(&mut self, dx: isize, dy: isize) -> bool {
+        match Self::index(dx, dy) {
+            Some(index) if self.cells[index].element != Element::Indestructible => {
+                self.cells.swap(4, index);
+                self.changed[4] = true;
+                self.changed[index] = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn random(&mut self) -> u32 {
+        self.random.next()
+    }
+
+    fn modify(&mut self, dx: isize, dy: isize, change: impl FnOnce(&mut Cell)) -> bool {
+        match Self::index(dx, dy) {
+            Some(index) if self.cells[index].element != Element::Indestructible => {
+                change(&mut self.cells[index]);
+                self.changed[index] = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn index(dx: isize, dy: isize) -> Option<usize> {
+        if (-1..=1).contains(&dx) && (-1..=1).contains(&dy) {
+            Some(((dx + 1) + (dy + 1) * 3) as usize)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use std::{
+    env,
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use bevy::prelude::*;
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+
+use crate::{
+    cell::Cell,
+    element::{Element, CUSTOM_ELEMENTS},
+    sandbox::{spawn_existing_sandbox, SandBox},
+    simulation::{simulation_system, Simulation},
+    toolbox::{Tool, ToolBox, EDITOR_ELEMENTS},
+};
+
+pub const DEFAULT_PORT: u16 = 7777;
+// Messages larger than this are refused, the largest sandbox fits easily
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+// Bytes per cell in the delta encoding: element, variant and strength
+const CELL_BYTES: usize = 3;
+
+// Size of an encoded tool application
+pub(crate) const APPLICATION_BYTES: usize = 11;
+
+// Message kinds, each message is sent as its length, kind and payload
+const SNAPSHOT: u8 = 1;
+const DELTA: u8 = 2;
+const APPLY: u8 = 3;
+
+// A tool used by a client at a cell, applied to the sandbox by the server
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ToolApplication {
+    pub tool: Tool,
+    // Air to erase
+    pub element: Element,
+    pub tool_size: usize,
+    pub x: usize,
+    pub y: usize,
+}
+
+impl ToolApplication {
+    pub(crate) fn to_bytes(self) -> Vec<u8> {
+        let tool = Tool::ALL
+            .iter()
+            .position(|tool| *tool == self.tool)
+            .unwrap() as u8;
+        let mut bytes = vec![tool, self.element as u8, self.tool_size.min(255) as u8];
+        bytes.extend_from_slice(&(self.x as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.y as u32).to_le_bytes());
+        bytes
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<ToolApplication> {
+        if bytes.len() != APPLICATION_BYTES {
+            return None;
+        }
+        // Only elements that can be drawn in the editor, e.g. no walls that cannot be erased
+        let element = Element::try_from(bytes[1]).ok()?;
+        if element != Element::Air
+            && !EDITOR_ELEMENTS.contains(&element)
+            && !CUSTOM_ELEMENTS.contains(&element)
+        {
+            return None;
+        }
+        Some(ToolApplication {
+            tool: *Tool::ALL.get(bytes[0] as usize)?,
+            element,
+            tool_size: (bytes[2] as usize).clamp(1, 64),
+            x: u32::from_le_bytes(bytes[3..7].try_into().unwrap()) as usize,
+            y: u32::from_le_bytes(bytes[7..11].try_into().unwrap()) as usize,
+        })
+    }
+}
+
+// Element, variant and strength of all cells, the basis of the delta encoding
+fn cell_bytes(sandbox: &SandBox) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(sandbox.width() * sandbox.height() * CELL_BYTES);
+    for y in 0..sandbox.height() {
+        for x in 0..sandbox.width() {
+            let cell = sandbox.get(x, y);
+            bytes.extend_from_slice(&[cell.element as u8, cell.variant, cell.strength]);
+        }
+    }
+    bytes
+}
+
+// The cell bytes XOR the previous ones, so unchanged cells are zeros that compress very well
+fn encode_delta(previous: &[u8], current: &[u8]) -> Vec<u8> {
+    let delta: Vec<u8> = previous
+        .iter()
+        .zip(current.iter())
+        .map(|(previous, current)| previous ^ current)
+        .collect();
+    compress_to_vec(&delta, 6)
+}
+
+// Apply a delta to the cells known by the client and the sandbox, returns None if it is invalid
+fn apply_delta(sandbox: &mut SandBox, replica: &mut [u8], delta: &[u8]) -> Option<()> {
+    // The cells known by the client have to be those of the sandbox, e.g. not of a new one
+    if replica.len() != sandbox.width() * sandbox.height() * CELL_BYTES {
+        return None;
+    }
+    let delta = decompress_to_vec_with_limit(delta, replica.len()).ok()?;
+    if delta.len() != replica.len() {
+        return None;
+    }
+    for (index, changes) in delta.chunks(CELL_BYTES).enumerate() {
+        if changes.iter().all(|change| *change == 0) {
+            continue;
+        }
+        let bytes = &mut replica[index * CELL_BYTES..(index + 1) * CELL_BYTES];
+        for (byte, change) in bytes.iter_mut().zip(changes) {
+            *byte ^= change;
+        }
+        let cell = Cell {
+            element: Element::try_from(bytes[0]).ok()?,
+            variant: bytes[1],
+            strength: bytes[2],
+            visited: false,
+        };
+        sandbox.set_cell(index % sandbox.width(), index / sandbox.width(), cell);
+    }
+    Some(())
+}
+
+// A non-blocking connection exchanging length-prefixed messages
+pub(crate) struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+}
+
+impl Connection {
+    pub(crate) fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        })
+    }
+
+    pub(crate) fn send(&mut self, kind: u8, payload: &[u8]) {
+        self.outgoing
+            .extend_from_slice(&(payload.len() as u32 + 1).to_le_bytes());
+        self.outgoing.push(kind);
+        self.outgoing.extend_from_slice(payload);
+    }
+
+    // Write as much of the queued messages as possible without blocking
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    // Read all available data and return the complete messages as kind and payload
+    pub(crate) fn receive(&mut self) -> io::Result<Vec<(u8, Vec<u8>)>> {
+        let mut buffer = [0; 16 * 1024];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(read) => self.incoming.extend_from_slice(&buffer[..read]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        let mut messages = Vec::new();
+        while self.incoming.len() >= 4 {
+            let length = u32::from_le_bytes(self.incoming[0..4].try_into().unwrap()) as usize;
+            if length == 0 || length > MAX_MESSAGE_SIZE {
+                return Err(ErrorKind::InvalidData.into());
+            }
+            if self.incoming.len() < 4 + length {
+                break;
+            }
+            let message: Vec<u8> = self.incoming.drain(..4 + length).skip(4).collect();
+            messages.push((message[0], message[1..].to_vec()));
+        }
+        Ok(messages)
+    }
+}
+
+// Server that owns the sandbox, applies the tools used by the clients and sends them the changes
+pub struct Server {
+    listener: TcpListener,
+    clients: Vec<(Connection, ToolBox)>,
+    // Cell bytes as last sent to the clients
+    previous: Vec<u8>,
+}
+
+impl Server {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(Server {
+            listener,
+            clients: Vec::new(),
+            previous: Vec::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    // Apply the tools the clients used since the last call, call before a simulation step
+    pub fn receive(&mut self, sandbox: &mut SandBox) {
+        self.clients.retain_mut(|(connection, toolbox)| {
+            let Ok(messages) = connection.receive() else {
+                return false;
+            };
+            for (kind, payload) in messages {
+                // Drop clients that send anything unexpected
+                let application = ToolApplication::from_bytes(&payload).filter(|application| {
+                    application.x < sandbox.width() && application.y < sandbox.height()
+                });
+                let (APPLY, Some(application)) = (kind, application) else {
+                    return false;
+                };
+                toolbox.tool = application.tool;
+                toolbox.element = application.element;
+                toolbox.tool_size = application.tool_size;
+                toolbox.apply(sandbox, application.x, application.y);
+            }
+            true
+        });
+    }
+
+    // Send the cells changed since the last call to the clients, and the complete sandbox to
+    // new clients. Call after a simulation step.
+    pub fn send_changes(&mut self, sandbox: &SandBox) {
+        let current = cell_bytes(sandbox);
+        if current.len() != self.previous.len() {
+            let snapshot = compress_to_vec(&sandbox.to_bytes(), 6);
+            for (connection, _) in self.clients.iter_mut() {
+                connection.send(SNAPSHOT, &snapshot);
+            }
+        } else if current != self.previous {
+            let delta = encode_delta(&self.previous, &current);
+            for (connection, _) in self.clients.iter_mut() {
+                connection.send(DELTA, &delta);
+            }
+        }
+        self.previous = current;
+
+        // New clients start from the state just sent to the others
+        while let Ok((stream, _)) = self.listener.accept() {
+            let Ok(mut connection) = Connection::new(stream) else {
+                continue;
+            };
+            connection.send(SNAPSHOT, &compress_to_vec(&sandbox.to_bytes(), 6));
+            self.clients.push((connection, ToolBox::default()));
+        }
+
+        self.clients
+            .retain_mut(|(connection, _)| connection.flush().is_ok());
+    }
+}
+
+// Client showing the sandbox of a server, sending it the tools used instead of applying them
+pub struct Client {
+    connection: Connection,
+    // Cell bytes as last received from the server
+    replica: Vec<u8>,
+}
+
+impl Client {
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Client {
+            connection: Connection::new(TcpStream::connect(address)?)?,
+            replica: Vec::new(),
+        })
+    }
+
+    pub fn send(&mut self, application: ToolApplication) -> io::Result<()> {
+        self.connection.send(APPLY, &application.to_bytes());
+        self.connection.flush()
+    }
+
+    // Apply the changes received from the server to the sandbox. Returns true when the
+    // server sent a complete sandbox, which may have a different size.
+    pub fn receive(&mut self, sandbox: &mut SandBox) -> io::Result<bool> {
+        self.connection.flush()?;
+        let invalid = || io::Error::from(ErrorKind::InvalidData);
+        let mut snapshot = false;
+        for (kind, payload) in self.connection.receive()? {
+            match kind {
+                SNAPSHOT => {
+                    let bytes = decompress_to_vec_with_limit(&payload, MAX_MESSAGE_SIZE)
+                        .map_err(|_| invalid())?;
+                    *sandbox = SandBox::from_bytes_with_border(&bytes).ok_or_else(invalid)?;
+                    self.replica = cell_bytes(sandbox);
+                    snapshot = true;
+                }
+                DELTA => {
+                    apply_delta(sandbox, &mut self.replica, &payload).ok_or_else(invalid)?;
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+/// Connects to a server given with `--connect <address>`, which then simulates the sandbox
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        let args: Vec<String> = env::args().collect();
+        let Some(address) = args
+            .iter()
+            .position(|arg| arg == "--connect")
+            .and_then(|index| args.get(index + 1))
+        else {
+            return;
+        };
+        let address = if address.contains(':') {
+            address.clone()
+        } else {
+            format!("{}:{}", address, DEFAULT_PORT)
+        };
+        match Client::connect(&address) {
+            Ok(client) => {
+                info!("Connected to {}", address);
+                app.insert_resource(NetworkClient(client))
+                    .init_resource::<RemoteTools>()
+                    .add_system(network_client_system.before(simulation_system));
+            }
+            Err(error) => error!("Could not connect to {}: {}", address, error),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct NetworkClient(pub Client);
+
+// Tools used in the editor that have to go over the network, present when connected
+#[derive(Resource, Default)]
+pub struct RemoteTools(pub Vec<ToolApplication>);
+
+// System showing the sandbox as simulated by the server
+fn network_client_system(
+    mut commands: Commands,
+    mut client: ResMut<NetworkClient>,
+    mut remote_tools: ResMut<RemoteTools>,
+    mut simulation: ResMut<Simulation>,
+    mut images: ResMut<Assets<Image>>,
+    mut sandbox: Query<(Entity, &mut SandBox)>,
+) {
+    // The server simulates, a local step would only be overwritten
+    simulation.running = false;
+    simulation.step = false;
+    for application in remote_tools.0.drain(..) {
+        if let Err(error) = client.0.send(application) {
+            error!("Could not send to the server: {}", error);
+        }
+    }
+    let Ok((entity, mut sandbox)) = sandbox.get_single_mut() else {
+        return;
+    };
+    let (width, height) = (sandbox.width(), sandbox.height());
+    match client.0.receive(&mut sandbox) {
+        Ok(true) if (sandbox.width(), sandbox.height()) != (width, height) => {
+            // The image has to match the new size
+            let sandbox = std::mem::replace(sandbox.as_mut(), SandBox::new(width, height));
+            commands.entity(entity).despawn();
+            spawn_existing_sandbox(commands, images.as_mut(), sandbox);
+        }
+        Ok(_) => {}
+        Err(error) => {
+            error!("Lost the connection to the server: {}", error);
+            commands.remove_resource::<NetworkClient>();
+            commands.remove_resource::<RemoteTools>();
+        }
+    }
+}
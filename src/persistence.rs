@@ -0,0 +1,284 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    camera::{CameraZoom, MAX_STORED_ZOOM, MIN_ZOOM},
+    element::Element,
+    language::Language,
+    network::NetworkClient,
+    sandbox::{spawn_existing_sandbox, SandBox, MAX_SANDBOX_SIZE},
+    settings::Settings,
+    theme::Theme,
+    toolbox::{Tool, ToolBox},
+};
+
+const CONFIG_NAME: &str = "config.txt";
+const SANDBOX_NAME: &str = "sandbox.dat";
+const AUTOSAVE_INTERVAL_SECONDS: f32 = 30.0;
+
+/// Plugin that remembers the settings, selected tool and camera zoom between runs,
+/// and optionally the sandbox itself. Stored in the user config directory, or in
+/// the local storage of the browser when run on the web.
+pub struct PersistencePlugin;
+
+impl Plugin for PersistencePlugin {
+    fn build(&self, app: &mut App) {
+        let mut settings = Settings::default();
+        let mut toolbox = ToolBox::default();
+        let mut zoom = 1.0;
+        let stored_config = storage::read_text(CONFIG_NAME).unwrap_or_default();
+        parse_config(&stored_config, &mut settings, &mut toolbox, &mut zoom);
+
+        app.insert_resource(settings)
+            .insert_resource(toolbox)
+            .insert_resource(PersistenceState {
+                zoom,
+                stored_config,
+                autosave_timer: Timer::from_seconds(
+                    AUTOSAVE_INTERVAL_SECONDS,
+                    TimerMode::Repeating,
+                ),
+            })
+            .add_startup_system(restore_session.in_base_set(StartupSet::PostStartup))
+            .add_system(store_session.in_base_set(CoreSet::Last));
+    }
+}
+
+#[derive(Resource)]
+struct PersistenceState {
+    zoom: f32,
+    stored_config: String,
+    autosave_timer: Timer,
+}
+
+// Apply the stored camera zoom and replace the new sandbox by the stored one (if any)
+fn restore_session(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut camera: Query<&mut Transform, With<Camera>>,
+    mut camera_zoom: ResMut<CameraZoom>,
+    sandbox: Query<Entity, With<SandBox>>,
+    settings: Res<Settings>,
+    state: Res<PersistenceState>,
+) {
+    if let Ok(mut transform) = camera.get_single_mut() {
+        transform.scale.x = state.zoom;
+        transform.scale.y = state.zoom;
+    }
+    camera_zoom.target = state.zoom;
+
+    if !settings.autosave || sandbox.is_empty() {
+        // Nothing to replace, e.g. in an endless world
+        return;
+    }
+    let stored_sandbox =
+        storage::read_bytes(SANDBOX_NAME).and_then(|bytes| SandBox::from_bytes_with_border(&bytes));
+    if let Some(stored_sandbox) = stored_sandbox {
+        for entity in sandbox.iter() {
+            commands.entity(entity).despawn();
+        }
+        spawn_existing_sandbox(commands, images.as_mut(), stored_sandbox);
+    }
+}
+
+// Store the configuration whenever it changes, and the sandbox periodically and on exit
+#[allow(clippy::too_many_arguments)]
+fn store_session(
+    mut state: ResMut<PersistenceState>,
+    mut app_exit_events: EventReader<AppExit>,
+    time: Res<Time>,
+    camera_zoom: Res<CameraZoom>,
+    sandbox: Query<&SandBox>,
+    settings: Res<Settings>,
+    toolbox: Res<ToolBox>,
+    client: Option<Res<NetworkClient>>,
+) {
+    let config = write_config(&settings, &toolbox, camera_zoom.target);
+    if config != state.stored_config {
+        storage::write_text(CONFIG_NAME, &config);
+        state.stored_config = config;
+    }
+
+    let exiting = app_exit_events.iter().count() > 0;
+    let autosave_due = state.autosave_timer.tick(time.delta()).just_finished();
+    if !exiting && !autosave_due || client.is_some() {
+        // The sandbox of a server is not stored in place of the own one
+        return;
+    }
+    if settings.autosave {
+        if let Ok(sandbox) = sandbox.get_single() {
+            storage::write_bytes(SANDBOX_NAME, &sandbox.to_bytes());
+        }
+    } else {
+        storage::remove(SANDBOX_NAME);
+    }
+}
+
+fn write_config(settings: &Settings, toolbox: &ToolBox, zoom: f32) -> String {
+    format!(
+        "language={}\nsandbox_size={}\nautosave={}\ngpu_render={}\nlighting={}\ntheme={}\nlocal_multiplayer={}\ntool={}\nelement={}\ntool_size={}\nzoom={}\n",
+        settings.language,
+        settings.sandbox_size,
+        settings.autosave,
+        settings.gpu_render,
+        settings.lighting,
+        settings.theme,
+        settings.local_multiplayer,
+        toolbox.tool,
+        toolbox.element,
+        toolbox.tool_size,
+        zoom
+    )
+}
+
+// Read simple key=value lines, ignoring anything that is unknown or invalid
+fn parse_config(config: &str, settings: &mut Settings, toolbox: &mut ToolBox, zoom: &mut f32) {
+    for line in config.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "language" => {
+                if let Ok(language) = value.parse::<Language>() {
+                    settings.language = language;
+                }
+            }
+            "sandbox_size" => {
+                if let Ok(size) = value.parse::<u32>() {
+                    settings.sandbox_size = size.clamp(64, MAX_SANDBOX_SIZE as u32);
+                }
+            }
+            "autosave" => {
+                if let Ok(autosave) = value.parse::<bool>() {
+                    settings.autosave = autosave;
+                }
+            }
+            "gpu_render" => {
+                if let Ok(gpu_render) = value.parse::<bool>() {
+                    settings.gpu_render = gpu_render;
+                }
+            }
+            "lighting" => {
+                if let Ok(lighting) = value.parse::<bool>() {
+                    settings.lighting = lighting;
+                }
+            }
+            "theme" => {
+                if let Ok(theme) = value.parse::<Theme>() {
+                    settings.theme = theme;
+                }
+            }
+            "local_multiplayer" => {
+                if let Ok(local_multiplayer) = value.parse::<bool>() {
+                    settings.local_multiplayer = local_multiplayer;
+                }
+            }
+            "tool" => {
+                if let Ok(tool) = value.parse::<Tool>() {
+                    toolbox.tool = tool;
+                }
+            }
+            "element" => {
+                if let Ok(element) = value.parse::<Element>() {
+                    toolbox.element = element;
+                }
+            }
+            "tool_size" => {
+                if let Ok(tool_size) = value.parse::<usize>() {
+                    toolbox.tool_size = tool_size.clamp(1, 64);
+                }
+            }
+            "zoom" => {
+                if let Ok(value) = value.parse::<f32>() {
+                    *zoom = value.clamp(MIN_ZOOM, MAX_STORED_ZOOM);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Storage in files in the user config directory
+#[cfg(not(target_family = "wasm"))]
+mod storage {
+    use std::{fs, path::PathBuf};
+
+    use bevy::prelude::warn;
+
+    fn path(name: &str) -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("falling-rust").join(name))
+    }
+
+    pub fn read_text(name: &str) -> Option<String> {
+        fs::read_to_string(path(name)?).ok()
+    }
+
+    pub fn read_bytes(name: &str) -> Option<Vec<u8>> {
+        fs::read(path(name)?).ok()
+    }
+
+    pub fn write_text(name: &str, text: &str) {
+        write_bytes(name, text.as_bytes());
+    }
+
+    pub fn write_bytes(name: &str, bytes: &[u8]) {
+        let Some(path) = path(name) else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Err(error) = fs::write(&path, bytes) {
+            warn!("Could not write {}: {}", path.display(), error);
+        }
+    }
+
+    pub fn remove(name: &str) {
+        if let Some(path) = path(name) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// Storage in the browser local storage, binary data is stored as hexadecimal text
+#[cfg(target_family = "wasm")]
+mod storage {
+    use web_sys::Storage;
+
+    fn local_storage() -> Option<Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    fn key(name: &str) -> String {
+        format!("falling-rust/{}", name)
+    }
+
+    pub fn read_text(name: &str) -> Option<String> {
+        local_storage()?.get_item(&key(name)).ok()?
+    }
+
+    pub fn read_bytes(name: &str) -> Option<Vec<u8>> {
+        let text = read_text(name)?;
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+
+    pub fn write_text(name: &str, text: &str) {
+        if let Some(storage) = local_storage() {
+            let _ = storage.set_item(&key(name), text);
+        }
+    }
+
+    pub fn write_bytes(name: &str, bytes: &[u8]) {
+        let text: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        write_text(name, &text);
+    }
+
+    pub fn remove(name: &str) {
+        if let Some(storage) = local_storage() {
+            let _ = storage.remove_item(&key(name));
+        }
+    }
+}
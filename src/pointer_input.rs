@@ -9,10 +9,14 @@ use bevy::{
 use bevy_egui::EguiContexts;
 
 use crate::{
+    camera::CameraZoom,
     element::Element,
     gui::{GuiMode, SandboxGui},
+    network::{RemoteTools, ToolApplication},
     sandbox::SandBox,
     toolbox::ToolBox,
+    touch::{touch_system, TouchGestures},
+    world::ChunkWorld,
     SystemOrderLabel,
 };
 
@@ -22,6 +26,8 @@ pub struct PointerInputPlugin;
 impl Plugin for PointerInputPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<PointerInputState>()
+            .init_resource::<TouchGestures>()
+            .add_system(touch_system.before(pointer_input))
             .add_system(pointer_input.in_set(SystemOrderLabel::PointerInput));
     }
 }
@@ -32,6 +38,8 @@ pub struct PointerInputState {
     pub middle_button_down: bool,
     pub right_button_down: bool,
     pub position: Vec2,
+    // Position in the previous frame, touches move the position before this system runs
+    pub last_position: Vec2,
     pub drag_movement: Vec2,
     pub world_position: Vec2,
 }
@@ -42,10 +50,13 @@ pub fn pointer_input(
     mut cursor_moved_events: EventReader<CursorMoved>,
     mut mouse_wheel_events: EventReader<MouseWheel>,
     mut camera: Query<(&Camera, &mut Transform, &GlobalTransform)>,
+    mut camera_zoom: ResMut<CameraZoom>,
     mut egui_context: EguiContexts,
     mut toolbox: ResMut<ToolBox>,
     mut sandbox: Query<&mut SandBox>,
     gui: Res<SandboxGui>,
+    mut remote_tools: Option<ResMut<RemoteTools>>,
+    mut world: Option<ResMut<ChunkWorld>>,
 ) {
     // Determine button state
     for event in mouse_button_input_events.iter() {
@@ -61,15 +72,15 @@ pub fn pointer_input(
     }
 
     // Record latest position
-    let last_position = mouse.position;
     for event in cursor_moved_events.iter() {
         mouse.position = event.position;
     }
     mouse.drag_movement = if mouse.left_button_down || mouse.middle_button_down {
-        last_position - mouse.position
+        mouse.last_position - mouse.position
     } else {
         Vec2::ZERO
     };
+    mouse.last_position = mouse.position;
 
     // Check mouse wheel
     let mut wheel_y = 0.0;
@@ -90,56 +101,98 @@ pub fn pointer_input(
         return;
     }
 
-    let sandbox = sandbox.get_single_mut();
-    if sandbox.is_err() {
+    let sandbox = sandbox.get_single_mut().ok();
+    if sandbox.is_none() && world.is_none() {
         // Sandbox not active
         return;
     }
 
-    let mut sandbox = sandbox.unwrap();
     // Update world position of the pointer (e.g. for use while editing the world)
     let (camera, mut transform, global_transform) = camera.single_mut();
-    let world_pos = camera
-        .viewport_to_world(global_transform, mouse.position)
-        .unwrap()
-        .origin;
-    mouse.world_position = Vec2::new(
-        world_pos.x + (sandbox.width() / 2) as f32,
-        (sandbox.height() / 2) as f32 - world_pos.y,
-    );
+    mouse.world_position = match sandbox.as_ref() {
+        Some(sandbox) => cell_position(camera, global_transform, sandbox, mouse.position),
+        None => world_cell_position(camera, global_transform, mouse.position),
+    };
 
-    // Zoom camera using mouse wheel
+    // Zoom camera around the pointer using mouse wheel
     if wheel_y > 0.0 {
-        transform.scale.x = (transform.scale.x * 0.9).clamp(0.1, 1.0);
-        transform.scale.y = (transform.scale.y * 0.9).clamp(0.1, 1.0);
+        camera_zoom.zoom_in(Some(mouse.position));
     } else if wheel_y < 0.0 {
-        transform.scale.x = (transform.scale.x * 1.1).clamp(0.1, 1.0);
-        transform.scale.y = (transform.scale.y * 1.1).clamp(0.1, 1.0);
+        camera_zoom.zoom_out(Some(mouse.position));
     }
 
-    // Pan camera
-    let half_width = (sandbox.width() / 2) as f32;
-    let half_height = (sandbox.height() / 2) as f32;
+    // Pan camera, the camera system keeps it within limits
     if mouse.middle_button_down || (gui.mode == GuiMode::MoveView && mouse.left_button_down) {
         transform.translation.x += mouse.drag_movement.x * transform.scale.x;
         transform.translation.y += mouse.drag_movement.y * transform.scale.y;
-
-        transform.translation.x = transform.translation.x.clamp(-half_width, half_width);
-        transform.translation.y = transform.translation.y.clamp(-half_height, half_height);
     }
 
     // Edit the world
+    if let Some(world) = world.as_mut() {
+        if gui.mode != GuiMode::MoveView && (mouse.left_button_down || mouse.right_button_down) {
+            let (x, y) = (mouse.world_position.x, mouse.world_position.y);
+            let erase = !mouse.left_button_down;
+            world.apply_tool(&mut toolbox, x.floor() as i64, y.floor() as i64, erase);
+        }
+        return;
+    }
+    let Some(mut sandbox) = sandbox else {
+        return;
+    };
     if gui.mode != GuiMode::MoveView {
         let (x, y) = (mouse.world_position.x, mouse.world_position.y);
         if x > 0.0 && x < sandbox.width() as f32 && y > 0.0 && y < sandbox.height() as f32 {
-            if mouse.left_button_down {
-                toolbox.apply(&mut sandbox, x.floor() as usize, y.floor() as usize);
+            let (x, y) = (x.floor() as usize, y.floor() as usize);
+            if let Some(remote_tools) = remote_tools.as_mut() {
+                // Applied when it comes back from the network
+                if mouse.left_button_down || mouse.right_button_down {
+                    remote_tools.0.push(ToolApplication {
+                        tool: toolbox.tool,
+                        element: if mouse.left_button_down {
+                            toolbox.element
+                        } else {
+                            Element::Air
+                        },
+                        tool_size: toolbox.tool_size,
+                        x,
+                        y,
+                    });
+                }
+            } else if mouse.left_button_down {
+                toolbox.apply(&mut sandbox, x, y);
             } else if mouse.right_button_down {
-                let element = toolbox.element;
-                toolbox.element = Element::Air;
-                toolbox.apply(&mut sandbox, x.floor() as usize, y.floor() as usize);
-                toolbox.element = element;
+                toolbox.erase(&mut sandbox, x, y);
             }
         }
     }
 }
+
+// Position in the sandbox, in cells, of a position in the window
+pub fn cell_position(
+    camera: &Camera,
+    global_transform: &GlobalTransform,
+    sandbox: &SandBox,
+    position: Vec2,
+) -> Vec2 {
+    let world_pos = camera
+        .viewport_to_world(global_transform, position)
+        .unwrap()
+        .origin;
+    Vec2::new(
+        world_pos.x + (sandbox.width() / 2) as f32,
+        (sandbox.height() / 2) as f32 - world_pos.y,
+    )
+}
+
+// Position in an endless world, in cells, of a position in the window
+pub fn world_cell_position(
+    camera: &Camera,
+    global_transform: &GlobalTransform,
+    position: Vec2,
+) -> Vec2 {
+    let world_pos = camera
+        .viewport_to_world(global_transform, position)
+        .unwrap()
+        .origin;
+    Vec2::new(world_pos.x, -world_pos.y)
+}
@@ -3,6 +3,12 @@ pub struct PseudoRandom {
     pub next: u32,
 }
 
+impl Default for PseudoRandom {
+    fn default() -> Self {
+        PseudoRandom::new()
+    }
+}
+
 // Quick and dirty pseudo-random number generator
 impl PseudoRandom {
     pub fn new() -> Self {
@@ -13,6 +19,8 @@ impl PseudoRandom {
         Self { next: seed }
     }
 
+    // Not an iterator, as the numbers never run out
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> u32 {
         self.next ^= self.next << 13;
         self.next ^= self.next >> 17;
@@ -1,19 +1,66 @@
 use crate::cell::Cell;
 use crate::element::{element_type, RenderMethod};
+use crate::gpu_render::is_gpu_rendered;
+use crate::lighting::{apply_light, LightMap};
 use crate::pseudo_random::PseudoRandom;
-use crate::sandbox::SandBox;
+use crate::sandbox::{SandBox, CHUNK_SIZE};
+use crate::settings::Settings;
+use crate::simulation::Simulation;
+use crate::theme::{pattern_value, Theme};
 use bevy::prelude::*;
 use bevy::utils::Instant;
 
 #[derive(Resource)]
 pub struct RenderState {
     pub random: PseudoRandom,
+    pub debug_view: DebugView,
+}
+
+// Alternative ways to show the cells, to see what the simulation is doing
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DebugView {
+    #[default]
+    None,
+    // Cells already updated in the current step versus cells still to be updated
+    Visited,
+    Strength,
+    Variant,
+    // Chunks of the sandbox in which cells changed since the previous frame
+    DirtyChunks,
+    // Time spent updating each cell in the last step
+    UpdateCost,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 6] = [
+        DebugView::None,
+        DebugView::Visited,
+        DebugView::Strength,
+        DebugView::Variant,
+        DebugView::DirtyChunks,
+        DebugView::UpdateCost,
+    ];
+
+    // Key of the name of this view in the language files
+    pub fn text_key(&self) -> &'static str {
+        match self {
+            DebugView::None => "debug.none",
+            DebugView::Visited => "debug.visited",
+            DebugView::Strength => "debug.strength",
+            DebugView::Variant => "debug.variant",
+            DebugView::DirtyChunks => "debug.dirty_chunks",
+            DebugView::UpdateCost => "debug.update_cost",
+        }
+    }
 }
 
 // "Render" the world by copying the element cells to pixels on a texture
 pub fn render_system(
     mut images: ResMut<Assets<Image>>,
     mut render_state: ResMut<RenderState>,
+    simulation: Res<Simulation>,
+    light_map: Res<LightMap>,
+    settings: Res<Settings>,
     mut sandbox: Query<(&mut SandBox, &Handle<Image>)>,
 ) {
     let sandbox = sandbox.get_single_mut();
@@ -23,15 +70,72 @@ pub fn render_system(
     }
     let (mut sandbox, image_handle) = sandbox.unwrap();
 
+    let debug_view = render_state.debug_view;
+    let theme = settings.theme;
     let random = &mut render_state.as_mut().random;
 
     let start = Instant::now();
 
+    let update_costs = simulation.update_costs.as_deref().unwrap_or_default();
+    let max_update_cost = update_costs.iter().copied().max().unwrap_or_default();
+
+    if is_gpu_rendered(images.get(image_handle).unwrap()) {
+        // The shader colors the cells, so only copy the changed ones. Changing the image uploads
+        // it again, so it is left alone while nothing changed.
+        if sandbox.is_dirty() {
+            let image = images.get_mut(image_handle).unwrap();
+            copy_dirty_cells(&sandbox, &mut image.data);
+            sandbox.clear_dirty_chunks();
+        }
+        sandbox.render_time_ms = (Instant::now() - start).as_millis();
+        return;
+    }
+
     let image = images.get_mut(image_handle).unwrap();
+
+    if debug_view == DebugView::None && light_map.is_empty() {
+        render_cells(&sandbox, theme, random, &mut image.data);
+        sandbox.clear_dirty_chunks();
+        sandbox.render_time_ms = (Instant::now() - start).as_millis();
+        return;
+    }
+
     for y in 0..sandbox.height() {
         for x in 0..sandbox.width() {
-            let cell = sandbox.get_mut(x, y);
-            let color = cell_color(cell, random);
+            let dirty = sandbox.is_chunk_dirty(x, y);
+            let cell = sandbox.get(x, y);
+            let color = match debug_view {
+                DebugView::None => {
+                    let (glow, sky) = light_map.light(x, y);
+                    apply_light(cell_color(cell, x, y, theme, random), glow, sky)
+                }
+                DebugView::Visited => {
+                    let color = cell_color(cell, x, y, theme, random);
+                    if sandbox.is_visited(x, y) {
+                        blend(&color, &(0, 255, 0))
+                    } else {
+                        blend(&color, &(255, 0, 0))
+                    }
+                }
+                DebugView::Strength => heat_color(cell.strength, u8::MAX),
+                DebugView::Variant => (cell.variant, cell.variant, cell.variant),
+                DebugView::DirtyChunks => {
+                    let color = cell_color(cell, x, y, theme, random);
+                    if dirty {
+                        blend(&color, &(255, 0, 255))
+                    } else {
+                        color
+                    }
+                }
+                DebugView::UpdateCost => {
+                    let cost = update_costs
+                        .get(x + y * sandbox.width())
+                        .copied()
+                        .unwrap_or_default();
+                    let scaled = cost as u64 * u8::MAX as u64 / max_update_cost.max(1) as u64;
+                    heat_color(scaled as u8, u8::MAX)
+                }
+            };
             let bytes_per_pixel = 4;
             let index = (x + y * sandbox.width()) * bytes_per_pixel;
             image.data[index] = color.0;
@@ -41,37 +145,124 @@ pub fn render_system(
         }
     }
 
+    sandbox.clear_dirty_chunks();
+
     let duration = Instant::now() - start;
     sandbox.render_time_ms = duration.as_millis();
 }
 
-pub fn cell_color(cell: &mut Cell, random: &mut PseudoRandom) -> (u8, u8, u8) {
-    let element_type = element_type(cell.element);
-    let color = match element_type.render {
-        RenderMethod::FixedColor => element_type.color_1,
-        RenderMethod::StrengthLinear => interpolate(
-            &element_type.color_1,
-            &element_type.color_2,
-            cell.strength,
-            element_type.strength,
-        ),
-        RenderMethod::VariantLinear => interpolate(
-            &element_type.color_1,
-            &element_type.color_2,
-            cell.variant,
-            u8::MAX,
-        ),
+// Copy the element, variant and strength of the cells in changed chunks to the image for the shader
+pub fn copy_dirty_cells(sandbox: &SandBox, data: &mut [u8]) {
+    let (width, height) = (sandbox.width(), sandbox.height());
+    for chunk_y in (0..height).step_by(CHUNK_SIZE) {
+        for chunk_x in (0..width).step_by(CHUNK_SIZE) {
+            if !sandbox.is_chunk_dirty(chunk_x, chunk_y) {
+                continue;
+            }
+            for y in chunk_y..(chunk_y + CHUNK_SIZE).min(height) {
+                for x in chunk_x..(chunk_x + CHUNK_SIZE).min(width) {
+                    let cell = sandbox.get(x, y);
+                    let index = (x + y * width) * 4;
+                    data[index..index + 4].copy_from_slice(&[
+                        cell.element as u8,
+                        cell.variant,
+                        cell.strength,
+                        255,
+                    ]);
+                }
+            }
+        }
+    }
+}
+
+// Colors of all cells as RGBA pixels, for exporting the sandbox as an image without a GPU
+pub fn render_colors(sandbox: &mut SandBox, theme: Theme) -> Vec<u8> {
+    let mut random = PseudoRandom::new();
+    let mut pixels = vec![0; sandbox.width() * sandbox.height() * 4];
+    render_cells(sandbox, theme, &mut random, &mut pixels);
+    pixels
+}
+
+// Write the colors of all cells as RGBA pixels. Runs of identical cells that are colored the
+// same everywhere, like empty space, get their color looked up once.
+pub fn render_cells(sandbox: &SandBox, theme: Theme, random: &mut PseudoRandom, pixels: &mut [u8]) {
+    let width = sandbox.width();
+    for y in 0..sandbox.height() {
+        let mut x = 0;
+        while x < width {
+            let cell = sandbox.get(x, y);
+            let length = match element_type(cell.element).render {
+                RenderMethod::FixedColor
+                | RenderMethod::StrengthLinear
+                | RenderMethod::VariantLinear => sandbox.run_length(x, y),
+                RenderMethod::Flicker | RenderMethod::Texture(_) => 1,
+            };
+            let index = (x + y * width) * 4;
+            fill_pixels(
+                &mut pixels[index..index + length * 4],
+                cell_pixel(cell, x, y, theme, random),
+            );
+            x += length;
+        }
+    }
+}
+
+pub fn cell_color(
+    cell: &Cell,
+    x: usize,
+    y: usize,
+    theme: Theme,
+    random: &mut PseudoRandom,
+) -> (u8, u8, u8) {
+    let [r, g, b, _] = cell_pixel(cell, x, y, theme, random);
+    (r, g, b)
+}
+
+// Color of a cell as an RGBA pixel, from the precomputed colors of its element
+#[inline]
+fn cell_pixel(cell: &Cell, x: usize, y: usize, theme: Theme, random: &mut PseudoRandom) -> [u8; 4] {
+    let colors = theme.cell_colors(cell.element);
+    match element_type(cell.element).render {
+        RenderMethod::FixedColor => colors[u8::MAX as usize],
+        RenderMethod::StrengthLinear => colors[cell.strength as usize],
+        RenderMethod::VariantLinear => colors[cell.variant as usize],
         RenderMethod::Flicker => {
-            cell.variant = (cell.variant + random.next() as u8) % u8::MAX;
-            interpolate(
-                &element_type.color_1,
-                &element_type.color_2,
-                cell.variant,
-                u8::MAX,
-            )
+            let variant = cell.variant.wrapping_add(random.next() as u8) % u8::MAX;
+            colors[variant as usize]
+        }
+        RenderMethod::Texture(pattern) => {
+            // Mostly the pattern, with a little variation per cell
+            let value = (pattern_value(pattern, x, y) as u16 * 3 + cell.variant as u16) / 4;
+            colors[value as usize]
+        }
+    }
+}
+
+// Write the same color to all RGBA pixels
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+fn fill_pixels(pixels: &mut [u8], color: [u8; 4]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.copy_from_slice(&color);
+    }
+}
+
+// Write the same color to all RGBA pixels, four pixels at a time
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+fn fill_pixels(pixels: &mut [u8], color: [u8; 4]) {
+    use std::arch::x86_64::{__m128i, _mm_set1_epi32, _mm_storeu_si128};
+
+    let mut blocks = pixels.chunks_exact_mut(16);
+    // Safety: SSE2 is available on all x86-64 processors, every block is 16 bytes long and the
+    // store needs no alignment
+    unsafe {
+        let value = _mm_set1_epi32(i32::from_ne_bytes(color));
+        for block in &mut blocks {
+            _mm_storeu_si128(block.as_mut_ptr() as *mut __m128i, value);
         }
-    };
-    color
+    }
+    for pixel in blocks.into_remainder().chunks_exact_mut(4) {
+        pixel.copy_from_slice(&color);
+    }
 }
 
 pub fn interpolate(
@@ -88,3 +279,18 @@ pub fn interpolate(
         (color_1.2 as f32 * factor_f32 + color_2.2 as f32 * inv_factor_f32) as u8,
     )
 }
+
+// Mix a color half-way with a highlight color
+fn blend(color: &(u8, u8, u8), highlight: &(u8, u8, u8)) -> (u8, u8, u8) {
+    interpolate(color, highlight, 1, 2)
+}
+
+// Color scale from black through red and yellow to white
+fn heat_color(value: u8, max: u8) -> (u8, u8, u8) {
+    let heat = value as u32 * 3 * 255 / max.max(1) as u32;
+    (
+        heat.min(255) as u8,
+        heat.saturating_sub(255).min(255) as u8,
+        heat.saturating_sub(510).min(255) as u8,
+    )
+}
@@ -4,13 +4,34 @@ use bevy::{
     render::render_resource::{Extent3d, TextureDimension, TextureFormat},
 };
 
+// Size of the square chunks used for tracking which parts of the sandbox changed
+pub const CHUNK_SIZE: usize = 16;
+
+// Start and multiplier of the FNV-1a hash, used for hashing the state
+const HASH_OFFSET: u64 = 0xcbf29ce484222325;
+const HASH_PRIME: u64 = 0x100000001b3;
+
+// Add a word to a hash in the way of FNV-1a, a word at a time instead of a byte for speed
+pub(crate) fn hash_word(hash: u64, word: u32) -> u64 {
+    (hash ^ word as u64).wrapping_mul(HASH_PRIME)
+}
+
+// Largest width and height of a sandbox
+pub const MAX_SANDBOX_SIZE: usize = 1024;
+
 // The sandbox consisting of a grid of cells with elements that is simulated
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct SandBox {
     width: usize,
     height: usize,
     cells: Vec<Cell>,
     visited_state: bool,
+    // Number of cells per element, kept up to date while editing
+    element_counts: [usize; ELEMENT_COUNT],
+    // Chunks in which cells changed since the flags were last cleared
+    dirty_chunks: Vec<bool>,
+    // Cells as they were before being changed, while recording changes
+    journal: Option<Vec<(usize, Cell)>>,
     pub render_time_ms: u128,
 }
 
@@ -29,7 +50,10 @@ impl SandBox {
         sandbox
     }
 
-    fn empty(width: usize, height: usize) -> Self {
+    // Sandbox without the border, e.g. for a part of a larger world
+    pub(crate) fn empty(width: usize, height: usize) -> Self {
+        let mut element_counts = [0; ELEMENT_COUNT];
+        element_counts[Element::Air as usize] = width * height;
         SandBox {
             width,
             height,
@@ -43,6 +67,9 @@ impl SandBox {
                 width * height
             ],
             visited_state: false,
+            element_counts,
+            dirty_chunks: vec![true; width.div_ceil(CHUNK_SIZE) * height.div_ceil(CHUNK_SIZE)],
+            journal: None,
             render_time_ms: 0,
         }
     }
@@ -52,20 +79,33 @@ impl SandBox {
         &self.cells[index]
     }
 
+    // Note: change the element of a cell using the methods of the sandbox, to keep the counts valid
     pub fn get_mut(&mut self, x: usize, y: usize) -> &mut Cell {
         let index = self.index(x, y);
         &mut self.cells[index]
     }
 
+    // Number of cells from this one to the right with the same element, variant and strength
+    pub fn run_length(&self, x: usize, y: usize) -> usize {
+        let index = self.index(x, y);
+        let cell = &self.cells[index];
+        self.cells[index..index + self.width - x]
+            .iter()
+            .take_while(|other| {
+                other.element == cell.element
+                    && other.variant == cell.variant
+                    && other.strength == cell.strength
+            })
+            .count()
+    }
+
     pub fn reduce_strength(&mut self, x: usize, y: usize, amount: u8) -> bool {
+        self.mark_dirty(x, y);
         let index = self.index(x, y);
+        self.record(index);
         let cell = &mut self.cells[index];
         if cell.strength > 0 {
-            cell.strength = if cell.strength > amount {
-                cell.strength - amount
-            } else {
-                0
-            };
+            cell.strength = cell.strength.saturating_sub(amount);
             true
         } else {
             false
@@ -85,16 +125,22 @@ impl SandBox {
         random: u32,
     ) {
         let index = self.index(x, y);
-        let mut cell = &mut self.cells[index];
+        let chunk = self.chunk_index(x, y);
+        let cell = &mut self.cells[index];
         if cell.element == Element::Indestructible {
             // Cannot edit these blocks
             return;
         }
+        self.dirty_chunks[chunk] = true;
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push((index, cell.clone()));
+        }
+        self.element_counts[cell.element as usize] -= 1;
+        self.element_counts[element as usize] += 1;
         cell.element = element;
         cell.visited = self.visited_state;
         cell.strength = strength;
-        let render = element_type(element).render;
-        if render == RenderMethod::VariantLinear || render == RenderMethod::Flicker {
+        if element_type(element).render.uses_variant() {
             cell.variant = random as u8;
         }
     }
@@ -103,6 +149,39 @@ impl SandBox {
         self.set_element_with_strength(x, y, element, element_type(element).strength, random);
     }
 
+    // Replace a cell, unless it is indestructible
+    pub fn set_cell(&mut self, x: usize, y: usize, cell: Cell) {
+        let index = self.index(x, y);
+        let chunk = self.chunk_index(x, y);
+        let target = &mut self.cells[index];
+        if target.element == Element::Indestructible {
+            return;
+        }
+        self.dirty_chunks[chunk] = true;
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push((index, target.clone()));
+        }
+        self.element_counts[target.element as usize] -= 1;
+        self.element_counts[cell.element as usize] += 1;
+        *target = cell;
+        target.visited = self.visited_state;
+    }
+
+    // Reduce the strength of a cell and turn it into the given element when zero
+    pub fn dissolve_to(&mut self, x: usize, y: usize, element: Element) -> bool {
+        self.mark_dirty(x, y);
+        let index = self.index(x, y);
+        self.record(index);
+        let cell = &mut self.cells[index];
+        let previous = cell.element;
+        let dissolved = cell.dissolve_to(element);
+        if dissolved {
+            self.element_counts[previous as usize] -= 1;
+            self.element_counts[element as usize] += 1;
+        }
+        dissolved
+    }
+
     pub fn swap(&mut self, x: usize, y: usize, x2: usize, y2: usize) {
         let index1 = self.index(x, y);
         let index2 = self.index(x2, y2);
@@ -112,11 +191,15 @@ impl SandBox {
             // Cannot edit these blocks
             return;
         }
+        self.record(index1);
+        self.record(index2);
         // cell is moved to the place of cell 2, so becomes the second cell
         cell.visited = self.visited_state;
         cell2.visited = self.visited_state;
         self.cells[index1] = cell2;
         self.cells[index2] = cell;
+        self.mark_dirty(x, y);
+        self.mark_dirty(x2, y2);
     }
 
     pub fn set_visited(&mut self, x: usize, y: usize) {
@@ -124,6 +207,83 @@ impl SandBox {
         self.cells[index].visited = self.visited_state;
     }
 
+    // Whether the cell was updated in the current simulation step
+    pub fn is_visited(&self, x: usize, y: usize) -> bool {
+        self.get(x, y).visited == self.visited_state
+    }
+
+    // Overwrite a cell including whether it was updated in the current simulation step, to copy
+    // cells between sandboxes
+    pub(crate) fn load_cell(&mut self, x: usize, y: usize, cell: &Cell, visited: bool) {
+        self.mark_dirty(x, y);
+        let index = self.index(x, y);
+        self.element_counts[self.cells[index].element as usize] -= 1;
+        self.element_counts[cell.element as usize] += 1;
+        self.cells[index] = Cell {
+            visited: if visited {
+                self.visited_state
+            } else {
+                !self.visited_state
+            },
+            ..cell.clone()
+        };
+    }
+
+    // Start or stop recording the changes to cells, so they can be inspected or undone
+    pub fn record_changes(&mut self, record: bool) {
+        self.journal = record.then(Vec::new);
+    }
+
+    fn record(&mut self, index: usize) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push((index, self.cells[index].clone()));
+        }
+    }
+
+    // Change in the number of cells per element since the recorded changes were last cleared
+    pub fn recorded_element_changes(&self) -> [i32; ELEMENT_COUNT] {
+        let mut changes = [0; ELEMENT_COUNT];
+        let Some(journal) = self.journal.as_ref() else {
+            return changes;
+        };
+        // Only the first change of a cell holds what it was before, the sort keeps the order of
+        // the changes to a cell
+        let mut first_changes: Vec<&(usize, Cell)> = journal.iter().collect();
+        first_changes.sort_by_key(|(index, _)| *index);
+        first_changes.dedup_by_key(|(index, _)| *index);
+        for (index, cell) in first_changes {
+            changes[cell.element as usize] -= 1;
+            changes[self.cells[*index].element as usize] += 1;
+        }
+        changes
+    }
+
+    pub fn has_recorded_changes(&self) -> bool {
+        self.journal
+            .as_ref()
+            .is_some_and(|journal| !journal.is_empty())
+    }
+
+    pub fn clear_recorded_changes(&mut self) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.clear();
+        }
+    }
+
+    // Restore the cells as they were before the recorded changes
+    pub fn undo_recorded_changes(&mut self) {
+        let Some(mut journal) = self.journal.take() else {
+            return;
+        };
+        for (index, cell) in journal.drain(..).rev() {
+            self.element_counts[self.cells[index].element as usize] -= 1;
+            self.element_counts[cell.element as usize] += 1;
+            self.cells[index] = cell;
+            self.mark_dirty(index % self.width, index / self.width);
+        }
+        self.journal = Some(journal);
+    }
+
     pub fn width(&self) -> usize {
         self.width
     }
@@ -145,11 +305,166 @@ impl SandBox {
         for y in 1..self.height - 1 {
             for x in 1..self.width - 1 {
                 let index = self.index(x, y);
-                let mut cell = &mut self.cells[index];
+                let cell = &mut self.cells[index];
                 cell.element = Element::Air;
                 cell.visited = self.visited_state;
             }
         }
+        self.count_elements();
+        self.dirty_chunks.fill(true);
+    }
+
+    // Whether any cell in the chunk containing this cell changed since the last clear
+    pub fn is_chunk_dirty(&self, x: usize, y: usize) -> bool {
+        self.dirty_chunks[self.chunk_index(x, y)]
+    }
+
+    // Whether any cell in the chunks overlapping an area changed since the last clear
+    pub fn is_area_dirty(&self, x1: usize, y1: usize, x2: usize, y2: usize) -> bool {
+        let (x2, y2) = (x2.min(self.width), y2.min(self.height));
+        if x1 >= x2 || y1 >= y2 {
+            return false;
+        }
+        (y1 / CHUNK_SIZE..=(y2 - 1) / CHUNK_SIZE).any(|chunk_y| {
+            (x1 / CHUNK_SIZE..=(x2 - 1) / CHUNK_SIZE)
+                .any(|chunk_x| self.is_chunk_dirty(chunk_x * CHUNK_SIZE, chunk_y * CHUNK_SIZE))
+        })
+    }
+
+    // Whether any cell changed since the last clear
+    pub fn is_dirty(&self) -> bool {
+        self.dirty_chunks.contains(&true)
+    }
+
+    pub fn clear_dirty_chunks(&mut self) {
+        self.dirty_chunks.fill(false);
+    }
+
+    // Mark every chunk as changed, e.g. when the rendered image has to be redrawn completely
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty_chunks.fill(true);
+    }
+
+    fn mark_dirty(&mut self, x: usize, y: usize) {
+        let chunk = self.chunk_index(x, y);
+        self.dirty_chunks[chunk] = true;
+    }
+
+    #[inline(always)]
+    fn chunk_index(&self, x: usize, y: usize) -> usize {
+        x / CHUNK_SIZE + (y / CHUNK_SIZE) * self.width.div_ceil(CHUNK_SIZE)
+    }
+
+    // Number of cells containing the given element
+    pub fn count(&self, element: Element) -> usize {
+        self.element_counts[element as usize]
+    }
+
+    // Number of cells per element, indexed by element
+    pub fn element_counts(&self) -> &[usize; ELEMENT_COUNT] {
+        &self.element_counts
+    }
+
+    fn count_elements(&mut self) {
+        self.element_counts = [0; ELEMENT_COUNT];
+        for cell in self.cells.iter() {
+            self.element_counts[cell.element as usize] += 1;
+        }
+    }
+
+    // Hash of the complete state that decides how the simulation continues, the same on every
+    // platform and between runs
+    pub fn state_hash(&self) -> u64 {
+        let mut hash = hash_word(HASH_OFFSET, self.width as u32);
+        hash = hash_word(hash, self.height as u32);
+        hash = hash_word(hash, self.visited_state as u32);
+        for cell in self.cells.iter() {
+            let word = cell.element as u32
+                | (cell.variant as u32) << 8
+                | (cell.strength as u32) << 16
+                | (cell.visited as u32) << 24;
+            hash = hash_word(hash, word);
+        }
+        hash
+    }
+
+    // Serialize the cells into a compact run-length encoded byte buffer
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(self.width as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.height as u32).to_le_bytes());
+        let mut run: Option<(u8, u8, u8, u8)> = None;
+        for cell in self.cells.iter() {
+            let (element, variant, strength) = (cell.element as u8, cell.variant, cell.strength);
+            run = match run {
+                Some((count, e, v, s))
+                    if count < u8::MAX && e == element && v == variant && s == strength =>
+                {
+                    Some((count + 1, e, v, s))
+                }
+                Some((count, e, v, s)) => {
+                    bytes.extend_from_slice(&[count, e, v, s]);
+                    Some((1, element, variant, strength))
+                }
+                None => Some((1, element, variant, strength)),
+            };
+        }
+        if let Some((count, e, v, s)) = run {
+            bytes.extend_from_slice(&[count, e, v, s]);
+        }
+        bytes
+    }
+
+    // Restore a sandbox written by to_bytes, returns None if the data is invalid. The border is not
+    // checked, e.g. for the chunks of a world which have none
+    pub fn from_bytes(bytes: &[u8]) -> Option<SandBox> {
+        if bytes.len() < 8 {
+            return None;
+        }
+        let width = u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as usize;
+        let height = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        if !(3..=MAX_SANDBOX_SIZE).contains(&width) || !(3..=MAX_SANDBOX_SIZE).contains(&height) {
+            return None;
+        }
+        // Check the number of cells before allocating, as the bytes may come from anywhere
+        let runs = bytes[8..].chunks(4);
+        let cells: usize = runs.clone().map(|run| run[0] as usize).sum();
+        if Some(cells) != width.checked_mul(height) {
+            return None;
+        }
+        let mut sandbox = SandBox::empty(width, height);
+        let mut index = 0;
+        for run in runs {
+            if run.len() < 4 {
+                return None;
+            }
+            let element = Element::try_from(run[1]).ok()?;
+            for _ in 0..run[0] {
+                let cell = sandbox.cells.get_mut(index)?;
+                cell.element = element;
+                cell.variant = run[2];
+                cell.strength = run[3];
+                index += 1;
+            }
+        }
+        if index != width * height {
+            return None;
+        }
+        sandbox.count_elements();
+        Some(sandbox)
+    }
+
+    // Restore a sandbox written by to_bytes, returns None if the data is invalid or the border is
+    // not indestructible, as the simulation does not check the bounds of the sandbox
+    pub fn from_bytes_with_border(bytes: &[u8]) -> Option<SandBox> {
+        let sandbox = SandBox::from_bytes(bytes)?;
+        let (width, height) = (sandbox.width(), sandbox.height());
+        let mut border = (0..width)
+            .flat_map(|x| [(x, 0), (x, height - 1)])
+            .chain((0..height).flat_map(|y| [(0, y), (width - 1, y)]));
+        border
+            .all(|(x, y)| sandbox.get(x, y).element == Element::Indestructible)
+            .then_some(sandbox)
     }
 
     #[inline(always)]
@@ -158,12 +473,24 @@ impl SandBox {
     }
 }
 
-pub fn spawn_sandbox(mut commands: Commands, images: &mut Assets<Image>, width: u32, height: u32) {
+pub fn spawn_sandbox(commands: Commands, images: &mut Assets<Image>, width: u32, height: u32) {
+    spawn_existing_sandbox(
+        commands,
+        images,
+        SandBox::new(width as usize, height as usize),
+    );
+}
+
+pub fn spawn_existing_sandbox(
+    mut commands: Commands,
+    images: &mut Assets<Image>,
+    sandbox: SandBox,
+) {
     let image_handle = {
         let image = Image::new_fill(
             Extent3d {
-                width,
-                height,
+                width: sandbox.width() as u32,
+                height: sandbox.height() as u32,
                 depth_or_array_layers: 1,
             },
             TextureDimension::D2,
@@ -172,14 +499,12 @@ pub fn spawn_sandbox(mut commands: Commands, images: &mut Assets<Image>, width:
         );
         images.add(image)
     };
-    commands
-        .spawn(SandBox::new(width as usize, height as usize))
-        .insert(SpriteBundle {
-            texture: image_handle,
-            transform: Transform {
-                translation: Vec3::new(0.0, 0.0, 0.0),
-                ..Default::default()
-            },
+    commands.spawn(sandbox).insert(SpriteBundle {
+        texture: image_handle,
+        transform: Transform {
+            translation: Vec3::new(0.0, 0.0, 0.0),
             ..Default::default()
-        });
+        },
+        ..Default::default()
+    });
 }
@@ -0,0 +1,457 @@
+use std::{
+    env, fs, mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use bevy::prelude::*;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST, INT};
+
+use crate::{
+    cell::Cell,
+    element::{Element, CUSTOM_ELEMENTS, ELEMENT_COUNT},
+    neighbourhood::Neighbourhood,
+    network::NetworkClient,
+    sandbox::SandBox,
+    simulation::{
+        frame_steps, measure_speed, simulation_step, simulation_system, CustomUpdate, Simulation,
+    },
+    toolbox::{Tool, ToolBox},
+};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+// The state a script works on. Only holds the sandbox while the script is running.
+#[derive(Default)]
+struct ScriptWorld {
+    sandbox: Option<SandBox>,
+    simulation: Simulation,
+    toolbox: ToolBox,
+}
+
+/// A Rhai script that drives the sandbox, for demos, experiments and puzzles.
+/// The main body runs once, after which an `on_step(step)` function (if defined)
+/// is called every time the simulation advanced.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    world: Arc<Mutex<ScriptWorld>>,
+    has_on_step: bool,
+}
+
+impl Script {
+    pub fn compile(source: &str) -> Result<Script, String> {
+        let world = Arc::new(Mutex::new(ScriptWorld::default()));
+        let engine = create_engine(&world);
+        let ast = engine.compile(source).map_err(|error| error.to_string())?;
+        let has_on_step = ast
+            .iter_functions()
+            .any(|function| function.name == "on_step" && function.params.len() == 1);
+        Ok(Script {
+            engine,
+            ast,
+            scope: Scope::new(),
+            world,
+            has_on_step,
+        })
+    }
+
+    // Run the main body of the script
+    pub fn run(
+        &mut self,
+        sandbox: &mut SandBox,
+        simulation: &mut Simulation,
+        toolbox: &mut ToolBox,
+    ) -> Result<(), String> {
+        self.with_world(sandbox, simulation, toolbox, |engine, ast, scope| {
+            engine.run_ast_with_scope(scope, ast)
+        })
+    }
+
+    // Call the on_step function of the script, if it has one
+    pub fn on_step(
+        &mut self,
+        step: u64,
+        sandbox: &mut SandBox,
+        simulation: &mut Simulation,
+        toolbox: &mut ToolBox,
+    ) -> Result<(), String> {
+        if !self.has_on_step {
+            return Ok(());
+        }
+        self.with_world(sandbox, simulation, toolbox, |engine, ast, scope| {
+            // Only call the function, without running the main body again
+            let options = CallFnOptions::new().eval_ast(false);
+            engine
+                .call_fn_with_options::<Dynamic>(options, scope, ast, "on_step", (step as INT,))
+                .map(|_| ())
+        })
+    }
+
+    // Lend the sandbox, simulation and toolbox to the script while running the given function
+    fn with_world(
+        &mut self,
+        sandbox: &mut SandBox,
+        simulation: &mut Simulation,
+        toolbox: &mut ToolBox,
+        function: impl FnOnce(&Engine, &AST, &mut Scope<'static>) -> ScriptResult<()>,
+    ) -> Result<(), String> {
+        {
+            let mut world = self.world.lock().unwrap();
+            world.sandbox = Some(mem::replace(sandbox, SandBox::new(3, 3)));
+            mem::swap(&mut world.simulation, simulation);
+            mem::swap(&mut world.toolbox, toolbox);
+        }
+        let result = function(&self.engine, &self.ast, &mut self.scope);
+        let mut world = self.world.lock().unwrap();
+        *sandbox = world.sandbox.take().unwrap();
+        mem::swap(&mut world.simulation, simulation);
+        mem::swap(&mut world.toolbox, toolbox);
+        result.map_err(|error| error.to_string())
+    }
+}
+
+fn create_engine(world: &Arc<Mutex<ScriptWorld>>) -> Engine {
+    let mut engine = Engine::new();
+
+    let w = world.clone();
+    engine.register_fn("width", move || -> ScriptResult<INT> {
+        with_sandbox(&w, |_, sandbox| Ok(sandbox.width() as INT))
+    });
+    let w = world.clone();
+    engine.register_fn("height", move || -> ScriptResult<INT> {
+        with_sandbox(&w, |_, sandbox| Ok(sandbox.height() as INT))
+    });
+    let w = world.clone();
+    engine.register_fn("get", move |x: INT, y: INT| -> ScriptResult<String> {
+        with_sandbox(&w, |_, sandbox| {
+            let (x, y) = coordinates(sandbox, x, y)?;
+            Ok(sandbox.get(x, y).element.to_string())
+        })
+    });
+    let w = world.clone();
+    engine.register_fn(
+        "set",
+        move |x: INT, y: INT, element: &str| -> ScriptResult<()> {
+            let element = parse_element(element)?;
+            with_sandbox(&w, |world, sandbox| {
+                let (x, y) = coordinates(sandbox, x, y)?;
+                sandbox.set_element(x, y, element, world.toolbox.random.next());
+                Ok(())
+            })
+        },
+    );
+    let w = world.clone();
+    engine.register_fn("count", move |element: &str| -> ScriptResult<INT> {
+        let element = parse_element(element)?;
+        with_sandbox(&w, |_, sandbox| Ok(sandbox.count(element) as INT))
+    });
+    let w = world.clone();
+    engine.register_fn("clear", move || -> ScriptResult<()> {
+        with_sandbox(&w, |_, sandbox| {
+            sandbox.clear();
+            Ok(())
+        })
+    });
+    let w = world.clone();
+    engine.register_fn("step", move || -> ScriptResult<()> {
+        with_sandbox(&w, |world, sandbox| {
+            world.simulation.step = true;
+            simulation_step(&mut world.simulation, sandbox);
+            Ok(())
+        })
+    });
+    let w = world.clone();
+    engine.register_fn("step", move |count: INT| -> ScriptResult<()> {
+        with_sandbox(&w, |world, sandbox| {
+            for _ in 0..count {
+                world.simulation.step = true;
+                simulation_step(&mut world.simulation, sandbox);
+            }
+            Ok(())
+        })
+    });
+    let w = world.clone();
+    engine.register_fn("step_count", move || -> INT {
+        w.lock().unwrap().simulation.step_count as INT
+    });
+    let w = world.clone();
+    engine.register_fn("tool", move |tool: &str| -> ScriptResult<()> {
+        let tool = tool
+            .parse::<Tool>()
+            .map_err(|_| format!("Unknown tool '{}'", tool))?;
+        w.lock().unwrap().toolbox.tool = tool;
+        Ok(())
+    });
+    let w = world.clone();
+    engine.register_fn("element", move |element: &str| -> ScriptResult<()> {
+        w.lock().unwrap().toolbox.element = parse_element(element)?;
+        Ok(())
+    });
+    let w = world.clone();
+    engine.register_fn("tool_size", move |size: INT| {
+        w.lock().unwrap().toolbox.tool_size = size.clamp(1, 64) as usize;
+    });
+    let w = world.clone();
+    engine.register_fn("apply", move |x: INT, y: INT| -> ScriptResult<()> {
+        with_sandbox(&w, |world, sandbox| {
+            let (x, y) = coordinates(sandbox, x, y)?;
+            world.toolbox.apply(sandbox, x, y);
+            Ok(())
+        })
+    });
+    engine.register_fn("elements", || -> rhai::Array {
+        (0..ELEMENT_COUNT as u8)
+            .filter_map(|value| Element::try_from(value).ok())
+            .map(|element| Dynamic::from(element.to_string()))
+            .collect()
+    });
+
+    engine
+}
+
+/// Behaviour of the custom elements, defined by `update_Custom1` to `update_Custom4`
+/// functions in a script. These get the neighbourhood of the cell as `this`.
+pub struct ScriptedElements {
+    engine: Engine,
+    ast: AST,
+    functions: Vec<(Element, String)>,
+    failed: AtomicBool,
+}
+
+impl ScriptedElements {
+    // Returns None if the script does not define the behaviour of any element
+    pub fn compile(source: &str) -> Result<Option<ScriptedElements>, String> {
+        let engine = create_element_engine();
+        let ast = engine.compile(source).map_err(|error| error.to_string())?;
+        let functions: Vec<_> = CUSTOM_ELEMENTS
+            .iter()
+            .map(|element| (*element, format!("update_{}", element)))
+            .filter(|(_, name)| {
+                ast.iter_functions()
+                    .any(|function| function.name == name && function.params.is_empty())
+            })
+            .collect();
+        if functions.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(ScriptedElements {
+            engine,
+            ast,
+            functions,
+            failed: AtomicBool::new(false),
+        }))
+    }
+}
+
+impl CustomUpdate for ScriptedElements {
+    fn defines(&self, element: Element) -> bool {
+        self.functions.iter().any(|(e, _)| *e == element)
+    }
+
+    fn update(&self, element: Element, neighbourhood: &mut Neighbourhood) {
+        if self.failed.load(Ordering::Relaxed) {
+            // Stop after the first error, instead of reporting it for every cell
+            return;
+        }
+        let Some((_, function)) = self.functions.iter().find(|(e, _)| *e == element) else {
+            return;
+        };
+        let mut this = Dynamic::from(neighbourhood.clone());
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut this);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut Scope::new(),
+            &self.ast,
+            function,
+            (),
+        );
+        match result {
+            Ok(_) => {
+                if let Some(updated) = this.try_cast::<Neighbourhood>() {
+                    *neighbourhood = updated;
+                }
+            }
+            Err(error) => {
+                if !self.failed.swap(true, Ordering::Relaxed) {
+                    error!("Element script error in {}: {}", function, error);
+                }
+            }
+        }
+    }
+}
+
+fn create_element_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .register_type_with_name::<Neighbourhood>("Neighbourhood")
+        .register_fn(
+            "get",
+            |n: &mut Neighbourhood, dx: INT, dy: INT| -> ScriptResult<String> {
+                Ok(neighbour(n, dx, dy)?.element.to_string())
+            },
+        )
+        .register_fn(
+            "strength",
+            |n: &mut Neighbourhood, dx: INT, dy: INT| -> ScriptResult<INT> {
+                Ok(neighbour(n, dx, dy)?.strength as INT)
+            },
+        )
+        .register_fn(
+            "variant",
+            |n: &mut Neighbourhood, dx: INT, dy: INT| -> ScriptResult<INT> {
+                Ok(neighbour(n, dx, dy)?.variant as INT)
+            },
+        )
+        .register_fn(
+            "set",
+            |n: &mut Neighbourhood, dx: INT, dy: INT, element: &str| -> ScriptResult<bool> {
+                Ok(n.set_element(dx as isize, dy as isize, parse_element(element)?))
+            },
+        )
+        .register_fn(
+            "set_strength",
+            |n: &mut Neighbourhood, dx: INT, dy: INT, strength: INT| {
+                n.set_strength(dx as isize, dy as isize, strength.clamp(0, 255) as u8)
+            },
+        )
+        .register_fn(
+            "set_variant",
+            |n: &mut Neighbourhood, dx: INT, dy: INT, variant: INT| {
+                n.set_variant(dx as isize, dy as isize, variant.clamp(0, 255) as u8)
+            },
+        )
+        .register_fn("swap", |n: &mut Neighbourhood, dx: INT, dy: INT| {
+            n.swap(dx as isize, dy as isize)
+        })
+        .register_fn("random", |n: &mut Neighbourhood, max: INT| -> INT {
+            if max > 0 {
+                n.random() as INT % max
+            } else {
+                0
+            }
+        });
+    engine
+}
+
+fn neighbour(neighbourhood: &Neighbourhood, dx: INT, dy: INT) -> ScriptResult<&Cell> {
+    neighbourhood
+        .get(dx as isize, dy as isize)
+        .ok_or_else(|| format!("Offset ({}, {}) is outside the neighbourhood", dx, dy).into())
+}
+
+fn with_sandbox<T>(
+    world: &Mutex<ScriptWorld>,
+    function: impl FnOnce(&mut ScriptWorld, &mut SandBox) -> ScriptResult<T>,
+) -> ScriptResult<T> {
+    let mut world = world.lock().unwrap();
+    let mut sandbox = world.sandbox.take().ok_or("The sandbox is not available")?;
+    let result = function(&mut world, &mut sandbox);
+    world.sandbox = Some(sandbox);
+    result
+}
+
+fn coordinates(sandbox: &SandBox, x: INT, y: INT) -> ScriptResult<(usize, usize)> {
+    if x < 0 || y < 0 || x >= sandbox.width() as INT || y >= sandbox.height() as INT {
+        return Err(format!("Position ({}, {}) is outside the sandbox", x, y).into());
+    }
+    Ok((x as usize, y as usize))
+}
+
+fn parse_element(name: &str) -> ScriptResult<Element> {
+    name.parse::<Element>()
+        .map_err(|_| format!("Unknown element '{}'", name).into())
+}
+
+/// Plugin that runs the script given as first command line argument in the sandbox editor
+pub struct ScriptPlugin;
+
+impl Plugin for ScriptPlugin {
+    fn build(&self, app: &mut App) {
+        let mut runner = ScriptRunner {
+            script: None,
+            elements: None,
+            started: false,
+        };
+        // Other arguments start with dashes, e.g. --connect
+        if let Some(path) = env::args().nth(1).filter(|arg| !arg.starts_with("--")) {
+            let compiled = fs::read_to_string(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|source| {
+                    Ok((
+                        Script::compile(&source)?,
+                        ScriptedElements::compile(&source)?,
+                    ))
+                });
+            match compiled {
+                Ok((script, elements)) => {
+                    runner.script = Some(script);
+                    runner.elements =
+                        elements.map(|elements| Arc::new(elements) as Arc<dyn CustomUpdate>);
+                }
+                Err(error) => error!("Could not load script {}: {}", path, error),
+            }
+        }
+        app.insert_resource(runner)
+            .add_system(script_system.before(simulation_system));
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct ScriptRunner {
+    script: Option<Script>,
+    elements: Option<Arc<dyn CustomUpdate>>,
+    started: bool,
+}
+
+// Whether a script simulates the steps itself, in place of the simulation system
+pub(crate) fn script_drives_simulation(runner: Res<ScriptRunner>) -> bool {
+    runner.script.is_some()
+}
+
+// Run the script body in the first frame, then simulate the steps of each frame one at a time,
+// notifying the script after every step
+fn script_system(
+    mut runner: ResMut<ScriptRunner>,
+    mut sandbox: Query<&mut SandBox>,
+    mut simulation: ResMut<Simulation>,
+    mut toolbox: ResMut<ToolBox>,
+    client: Option<Res<NetworkClient>>,
+    time: Res<Time>,
+) {
+    if client.is_some() {
+        // The server owns the sandbox, changes by the script would only be overwritten
+        return;
+    }
+    let runner = runner.as_mut();
+    let (Some(script), Ok(mut sandbox)) = (runner.script.as_mut(), sandbox.get_single_mut()) else {
+        return;
+    };
+    let result = if !runner.started {
+        runner.started = true;
+        simulation.custom_update = runner.elements.take();
+        script.run(&mut sandbox, &mut simulation, &mut toolbox)
+    } else {
+        let steps = frame_steps(&mut simulation, &time);
+        let start = Instant::now();
+        // The script sees every step, also when several steps are simulated in this frame
+        let result = (0..steps).try_for_each(|_| {
+            simulation_step(&mut simulation, &mut sandbox);
+            let step = simulation.step_count;
+            script.on_step(step, &mut sandbox, &mut simulation, &mut toolbox)
+        });
+        simulation.frame_time_ms = (Instant::now() - start).as_millis();
+        measure_speed(&mut simulation, steps, &time);
+        result
+    };
+    if let Err(error) = result {
+        error!("Script error: {}", error);
+        runner.script = None;
+    }
+}
@@ -1,18 +1,32 @@
 use bevy::prelude::Resource;
 
-use crate::language::Language;
+use crate::{language::Language, theme::Theme};
 
 #[derive(Resource)]
 pub struct Settings {
     pub language: Language,
     pub sandbox_size: u32,
+    // Store the sandbox on exit and restore it on the next start
+    pub autosave: bool,
+    // Color the cells with a shader instead of on the CPU
+    pub gpu_render: bool,
+    // Light up glowing elements and darken where the sky does not reach, slow on the web
+    pub lighting: bool,
+    pub theme: Theme,
+    // Every gamepad controls its own cursor and tools, instead of sharing those of the mouse
+    pub local_multiplayer: bool,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Settings {
-            language: Language::English,
+            language: Language::detect(),
             sandbox_size: 256,
+            autosave: true,
+            gpu_render: true,
+            lighting: !cfg!(target_family = "wasm"),
+            theme: Theme::default(),
+            local_multiplayer: false,
         }
     }
 }
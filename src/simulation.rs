@@ -1,8 +1,25 @@
+use crate::conservation::MassBalance;
 use crate::element::*;
+use crate::neighbourhood::Neighbourhood;
 use crate::pseudo_random::PseudoRandom;
 use crate::sandbox::*;
 use bevy::prelude::*;
 use bevy::utils::Instant;
+use std::sync::Arc;
+
+// Simulation steps per second at normal speed when using a fixed time step
+pub const BASE_STEPS_PER_SECOND: f32 = 60.0;
+// Limit the steps per frame, so a slow simulation cannot make each frame slower still
+const MAX_STEPS_PER_FRAME: u32 = 16;
+
+// Behaviour of the custom elements, e.g. defined in a script
+pub trait CustomUpdate: Send + Sync {
+    // Whether this defines behaviour for the given element
+    fn defines(&self, element: Element) -> bool;
+
+    // Update the cell in the center of the neighbourhood
+    fn update(&self, element: Element, neighbourhood: &mut Neighbourhood);
+}
 
 #[derive(Clone, Resource)]
 pub struct Simulation {
@@ -10,6 +27,23 @@ pub struct Simulation {
     pub step: bool,
     pub frame_time_ms: u128,
     pub random: PseudoRandom,
+    // Number of simulation steps done so far
+    pub step_count: u64,
+    // Speed multiplier, below 1 for slow motion and above 1 for fast-forward
+    pub speed: f32,
+    // Step at a fixed rate independent of the frame rate, instead of once per frame
+    pub fixed_timestep: bool,
+    // Measured simulation steps per second
+    pub steps_per_second: f32,
+    // Behaviour of the custom elements, which do nothing without it
+    pub custom_update: Option<Arc<dyn CustomUpdate>>,
+    // Time in nanoseconds spent updating each cell in the last step, only measured when set
+    pub update_costs: Option<Vec<u32>>,
+    // Mass created and destroyed per element, only tracked when set
+    pub mass_balance: Option<MassBalance>,
+    step_accumulator: f32,
+    measure_steps: u64,
+    measure_seconds: f32,
 }
 
 impl Default for Simulation {
@@ -25,19 +59,74 @@ impl Simulation {
             step: false,
             frame_time_ms: 0,
             random: PseudoRandom::new(),
+            step_count: 0,
+            speed: 1.0,
+            fixed_timestep: false,
+            steps_per_second: 0.0,
+            custom_update: None,
+            update_costs: None,
+            mass_balance: None,
+            step_accumulator: 0.0,
+            measure_steps: 0,
+            measure_seconds: 0.0,
         }
     }
+
+    // Hash of the sandbox together with the random number generator, which is all that decides
+    // the outcome of the next steps
+    pub fn state_hash(&self, sandbox: &SandBox) -> u64 {
+        hash_word(sandbox.state_hash(), self.random.next)
+    }
 }
 
-// System used to simulate the world a single step each frame
-pub fn simulation_system(mut sandbox: Query<&mut SandBox>, mut simulation: ResMut<Simulation>) {
-    match sandbox.get_single_mut() {
-        Ok(mut sandbox) => {
-            simulation_step(simulation.as_mut(), sandbox.as_mut());
-        }
+// System used to simulate the world, a number of steps each frame depending on the speed
+pub fn simulation_system(
+    mut sandbox: Query<&mut SandBox>,
+    mut simulation: ResMut<Simulation>,
+    time: Res<Time>,
+) {
+    let mut sandbox = match sandbox.get_single_mut() {
+        Ok(sandbox) => sandbox,
         Err(_) => {
             return;
         }
+    };
+    let simulation = simulation.as_mut();
+    let steps = frame_steps(simulation, &time);
+
+    let start = Instant::now();
+    for _ in 0..steps {
+        simulation_step(simulation, sandbox.as_mut());
+    }
+    simulation.frame_time_ms = (Instant::now() - start).as_millis();
+    measure_speed(simulation, steps, &time);
+}
+
+// Number of steps to simulate in this frame, depending on the speed
+pub(crate) fn frame_steps(simulation: &mut Simulation, time: &Time) -> u32 {
+    if simulation.running {
+        simulation.step_accumulator += if simulation.fixed_timestep {
+            time.delta_seconds() * BASE_STEPS_PER_SECOND * simulation.speed
+        } else {
+            simulation.speed
+        };
+        let steps = (simulation.step_accumulator as u32).min(MAX_STEPS_PER_FRAME);
+        simulation.step_accumulator = (simulation.step_accumulator - steps as f32).min(1.0);
+        steps
+    } else {
+        simulation.step_accumulator = 0.0;
+        u32::from(simulation.step)
+    }
+}
+
+// Measure the effective simulation speed about once a second
+pub(crate) fn measure_speed(simulation: &mut Simulation, steps: u32, time: &Time) {
+    simulation.measure_steps += steps as u64;
+    simulation.measure_seconds += time.delta_seconds();
+    if simulation.measure_seconds >= 1.0 {
+        simulation.steps_per_second = simulation.measure_steps as f32 / simulation.measure_seconds;
+        simulation.measure_steps = 0;
+        simulation.measure_seconds = 0.0;
     }
 }
 
@@ -45,26 +134,73 @@ pub fn simulation_step(mut simulation: &mut Simulation, sandbox: &mut SandBox) {
     let start = Instant::now();
     if simulation.running || simulation.step {
         simulation.step = false;
-        let visited = sandbox.toggle_visited_state();
-        let (width, height) = (sandbox.width() - 1, sandbox.height() - 1);
-        for y in (1..height).rev() {
+        simulation.step_count += 1;
+        let forward = sandbox.toggle_visited_state();
+        if let Some(mass_balance) = simulation.mass_balance.as_mut() {
+            mass_balance.start_step();
+            sandbox.record_changes(true);
+        }
+        update_cells(simulation, sandbox, forward);
+        if simulation.mass_balance.is_some() {
+            sandbox.record_changes(false);
+        }
+    }
+    let duration = Instant::now() - start;
+    simulation.frame_time_ms = duration.as_millis();
+}
+
+// Update every cell within the border of the sandbox once, from the bottom up
+pub(crate) fn update_cells(simulation: &mut Simulation, sandbox: &mut SandBox, forward: bool) {
+    let custom_update = simulation.custom_update.clone();
+    let custom_update = custom_update.as_deref();
+    if let Some(update_costs) = simulation.update_costs.as_mut() {
+        update_costs.resize(sandbox.width() * sandbox.height(), 0);
+    }
+    let (width, height) = (sandbox.width() - 1, sandbox.height() - 1);
+    for y in (1..height).rev() {
+        for i in 1..width {
             // Switch X order every frame to avoid simulation artifacts
-            if visited {
-                for x in 1..width {
-                    update_cell(x, y, sandbox, simulation.random.next());
-                }
+            let x = if forward { i } else { width - i };
+            let random = simulation.random.next();
+            // Air only comes alive next to life, so count that as a reaction of life
+            let cause = simulation
+                .mass_balance
+                .as_ref()
+                .map(|_| match sandbox.get(x, y).element {
+                    Element::Air => Element::Life,
+                    element => element,
+                });
+            if let Some(update_costs) = simulation.update_costs.as_mut() {
+                let start = Instant::now();
+                update_cell(x, y, sandbox, random, custom_update);
+                update_costs[x + y * sandbox.width()] = start.elapsed().as_nanos() as u32;
             } else {
-                for x in (1..width).rev() {
-                    update_cell(x, y, sandbox, simulation.random.next());
-                }
+                update_cell(x, y, sandbox, random, custom_update);
+            }
+            if let (Some(mass_balance), Some(cause)) = (simulation.mass_balance.as_mut(), cause) {
+                account_mass(mass_balance, sandbox, cause);
             }
         }
     }
-    let duration = Instant::now() - start;
-    simulation.frame_time_ms = duration.as_millis();
 }
 
-fn update_cell(x: usize, y: usize, sandbox: &mut SandBox, random: u32) {
+fn account_mass(mass_balance: &mut MassBalance, sandbox: &mut SandBox, cause: Element) {
+    if !sandbox.has_recorded_changes() {
+        return;
+    }
+    if !mass_balance.update(cause, &sandbox.recorded_element_changes()) {
+        sandbox.undo_recorded_changes();
+    }
+    sandbox.clear_recorded_changes();
+}
+
+fn update_cell(
+    x: usize,
+    y: usize,
+    sandbox: &mut SandBox,
+    random: u32,
+    custom_update: Option<&dyn CustomUpdate>,
+) {
     // Step 1: handle interactions with surrounding cells
     let cell = sandbox.get(x, y).clone();
     if cell.visited == sandbox.is_visited_state() {
@@ -101,6 +237,9 @@ fn update_cell(x: usize, y: usize, sandbox: &mut SandBox, random: u32) {
         Element::Seed => update_seed(x, y, sandbox, random),
         Element::TNT => update_tnt(x, y, sandbox, random),
         Element::Explosion => update_explosion(x, y, sandbox, random),
+        Element::Custom1 | Element::Custom2 | Element::Custom3 | Element::Custom4 => {
+            update_custom(x, y, sandbox, random, cell.element, custom_update)
+        }
         _ => false,
     };
 
@@ -137,9 +276,9 @@ fn handle_igniting_cell(x: usize, y: usize, sandbox: &mut SandBox, random: u32)
             );
         } else if neighbour_type.has_flag(FLAG_BURNS) {
             if neighbour_type.has_flag(FLAG_TURNS_INTO_ASH) && once_per(random, 3) {
-                sandbox.get_mut(nx, ny).dissolve_to(Element::Ash);
+                sandbox.dissolve_to(nx, ny, Element::Ash);
             } else {
-                sandbox.get_mut(nx, ny).dissolve_to(Element::Fire);
+                sandbox.dissolve_to(nx, ny, Element::Fire);
             }
         }
     }
@@ -152,7 +291,7 @@ fn handle_acidic_cell(x: usize, y: usize, sandbox: &mut SandBox, random: u32) {
         if neighbour_type.has_flag(FLAG_DISSOLVES_IN_ACID)
             && once_per(random, (neighbour_cell.strength / 2).max(2) as u32)
         {
-            if sandbox.get_mut(nx, ny).dissolve_to(Element::Air) {
+            if sandbox.dissolve_to(nx, ny, Element::Air) {
                 if once_per(random, 2) {
                     sandbox.set_element(x, y, Element::Smoke, random);
                 } else {
@@ -304,11 +443,11 @@ fn update_water(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool
     let neighbour_element = sandbox.get(nx, ny).element;
     match neighbour_element {
         Element::Acid => {
-            sandbox.get_mut(nx, ny).dissolve_to(Element::Water);
+            sandbox.dissolve_to(nx, ny, Element::Water);
             return false;
         }
         Element::Lava => {
-            if sandbox.get_mut(nx, ny).dissolve_to(Element::Rock) {
+            if sandbox.dissolve_to(nx, ny, Element::Rock) {
                 sandbox.clear_cell(x, y);
             }
             return false;
@@ -345,7 +484,7 @@ fn update_drain(x: usize, y: usize, sandbox: &mut SandBox, _random: u32) -> bool
 
 fn update_fire(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
     // Reduce fire strength over time
-    if once_per(random, 2) && sandbox.get_mut(x, y).dissolve_to(Element::Air) {
+    if once_per(random, 2) && sandbox.dissolve_to(x, y, Element::Air) {
         sandbox.set_element(x, y, Element::Smoke, random);
         return true;
     }
@@ -353,7 +492,7 @@ fn update_fire(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
 }
 
 fn update_ash(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
-    if once_per(random, 100) && sandbox.get_mut(x, y).dissolve_to(Element::Air) {
+    if once_per(random, 100) && sandbox.dissolve_to(x, y, Element::Air) {
         return true;
     }
     false
@@ -363,7 +502,7 @@ fn update_lava(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
     let cell = sandbox.get_mut(x, y);
     // Cool down when no longer at max hotness
     if once_per(random, 2) && cell.strength < element_type(Element::Lava).strength {
-        if sandbox.get_mut(x, y).dissolve_to(Element::Rock) {
+        if sandbox.dissolve_to(x, y, Element::Rock) {
             return true;
         }
     }
@@ -375,7 +514,7 @@ fn update_lava(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
 }
 
 fn update_smoke(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
-    if once_per(random, 2) && sandbox.get_mut(x, y).dissolve_to(Element::Air) {
+    if once_per(random, 2) && sandbox.dissolve_to(x, y, Element::Air) {
         sandbox.clear_cell(x, y);
         return true;
     }
@@ -549,6 +688,24 @@ fn update_explosion(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> b
     true
 }
 
+fn update_custom(
+    x: usize,
+    y: usize,
+    sandbox: &mut SandBox,
+    random: u32,
+    element: Element,
+    custom_update: Option<&dyn CustomUpdate>,
+) -> bool {
+    match custom_update {
+        Some(custom_update) if custom_update.defines(element) => {
+            let mut neighbourhood = Neighbourhood::read(sandbox, x, y, random);
+            custom_update.update(element, &mut neighbourhood);
+            neighbourhood.write(sandbox, x, y)
+        }
+        _ => false,
+    }
+}
+
 fn update_air(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
     let mut living_neighbours = 0;
     if sandbox.get(x - 1, y - 1).element == Element::Life {
@@ -0,0 +1,44 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{element::ELEMENT_COUNT, sandbox::SandBox, simulation::Simulation};
+
+// Number of samples kept for the population graphs
+const MAX_SAMPLES: usize = 600;
+
+// Population of each element over time, sampled after every simulated frame
+#[derive(Resource, Default)]
+pub struct Statistics {
+    samples: VecDeque<(u64, [usize; ELEMENT_COUNT])>,
+}
+
+impl Statistics {
+    // Samples of (simulation step, cell count per element), oldest first
+    pub fn samples(&self) -> impl Iterator<Item = &(u64, [usize; ELEMENT_COUNT])> {
+        self.samples.iter()
+    }
+
+    pub fn latest(&self) -> Option<&[usize; ELEMENT_COUNT]> {
+        self.samples.back().map(|(_, counts)| counts)
+    }
+}
+
+pub fn statistics_system(
+    mut statistics: ResMut<Statistics>,
+    simulation: Res<Simulation>,
+    sandbox: Query<&SandBox>,
+) {
+    let Ok(sandbox) = sandbox.get_single() else {
+        return;
+    };
+    if statistics.samples.back().map(|(step, _)| *step) == Some(simulation.step_count) {
+        return;
+    }
+    if statistics.samples.len() >= MAX_SAMPLES {
+        statistics.samples.pop_front();
+    }
+    statistics
+        .samples
+        .push_back((simulation.step_count, *sandbox.element_counts()));
+}
@@ -0,0 +1,246 @@
+use std::{fmt, str::FromStr, sync::OnceLock};
+
+use crate::element::{element_type, Element, Pattern, RenderMethod, ELEMENT_COUNT};
+
+// Width and height of the tiled pattern images
+pub const PATTERN_SIZE: usize = 16;
+
+type Gradient = Vec<(u8, u8, u8)>;
+type ColorTable = [[u8; 4]; 256];
+
+// Global color schemes for all elements
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Theme {
+    // The colors defined by the elements themselves
+    #[default]
+    Classic,
+    HighContrast,
+    // Based on the Okabe-Ito palette, distinguishable with the common forms of colour blindness
+    ColourBlind,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Classic, Theme::HighContrast, Theme::ColourBlind];
+
+    // Key of the name of this theme in the language files
+    pub fn text_key(&self) -> &'static str {
+        match self {
+            Theme::Classic => "theme.classic",
+            Theme::HighContrast => "theme.high_contrast",
+            Theme::ColourBlind => "theme.colour_blind",
+        }
+    }
+
+    // Gradient of colors of an element, from low to high values
+    pub fn gradient(&self, element: Element) -> &'static [(u8, u8, u8)] {
+        static GRADIENTS: OnceLock<Vec<Vec<Gradient>>> = OnceLock::new();
+        let gradients = GRADIENTS.get_or_init(|| {
+            Theme::ALL
+                .iter()
+                .map(|theme| {
+                    (0..ELEMENT_COUNT as u8)
+                        .map(|value| match Element::try_from(value) {
+                            Ok(element) => theme.create_gradient(element),
+                            Err(_) => vec![(0, 0, 0)],
+                        })
+                        .collect()
+                })
+                .collect()
+        });
+        &gradients[*self as usize][element as usize]
+    }
+
+    // RGBA colors of an element for each value of the cell it is rendered by, the strength for
+    // elements rendered by strength and the variant for all others
+    pub fn cell_colors(&self, element: Element) -> &'static ColorTable {
+        static COLORS: OnceLock<Vec<Vec<ColorTable>>> = OnceLock::new();
+        let colors = COLORS.get_or_init(|| {
+            Theme::ALL
+                .iter()
+                .map(|theme| {
+                    (0..ELEMENT_COUNT as u8)
+                        .map(|value| match Element::try_from(value) {
+                            Ok(element) => theme.create_cell_colors(element),
+                            Err(_) => [[0, 0, 0, 255]; 256],
+                        })
+                        .collect()
+                })
+                .collect()
+        });
+        &colors[*self as usize][element as usize]
+    }
+
+    fn create_cell_colors(&self, element: Element) -> ColorTable {
+        let gradient = self.gradient(element);
+        let max = match element_type(element).render {
+            RenderMethod::StrengthLinear => element_type(element).strength,
+            _ => u8::MAX,
+        };
+        let mut colors = [[0; 4]; 256];
+        for (value, color) in colors.iter_mut().enumerate() {
+            let (r, g, b) = gradient_color(gradient, value as u8, max);
+            *color = [r, g, b, 255];
+        }
+        colors
+    }
+
+    fn create_gradient(&self, element: Element) -> Gradient {
+        let element_type = element_type(element);
+        let base = match self {
+            Theme::Classic if element_type.palette.is_empty() => {
+                return vec![element_type.color_2, element_type.color_1];
+            }
+            Theme::Classic => return element_type.palette.to_vec(),
+            Theme::HighContrast => high_contrast_color(element),
+            Theme::ColourBlind => colour_blind_color(element),
+        };
+        // A darker shade of the same color, so variations stay visible
+        let dark = (
+            (base.0 as u16 * 3 / 4) as u8,
+            (base.1 as u16 * 3 / 4) as u8,
+            (base.2 as u16 * 3 / 4) as u8,
+        );
+        vec![dark, base]
+    }
+}
+
+fn high_contrast_color(element: Element) -> (u8, u8, u8) {
+    match element {
+        Element::Air => (0, 0, 0),
+        Element::Sand => (255, 220, 0),
+        Element::Rock => (160, 160, 160),
+        Element::Water => (0, 90, 255),
+        Element::Acid => (0, 255, 0),
+        Element::Drain => (60, 60, 60),
+        Element::Wood => (170, 90, 0),
+        Element::Iron => (220, 220, 255),
+        Element::Rust => (200, 60, 0),
+        Element::Fire => (255, 60, 0),
+        Element::Ash => (255, 255, 255),
+        Element::Oil => (140, 0, 200),
+        Element::Lava => (255, 120, 0),
+        Element::Smoke => (110, 110, 110),
+        Element::Life => (255, 0, 255),
+        Element::Seed => (200, 255, 120),
+        Element::Plant => (0, 200, 0),
+        Element::TNT => (255, 0, 0),
+        Element::Fuse => (255, 160, 160),
+        Element::Explosion => (255, 255, 0),
+        Element::WaterSource => (0, 255, 255),
+        Element::AcidSource => (120, 255, 120),
+        Element::OilSource => (200, 100, 255),
+        Element::FireSource => (255, 100, 60),
+        Element::LavaSource => (255, 180, 60),
+        Element::Indestructible => (90, 90, 120),
+        Element::Custom1 => (255, 100, 200),
+        Element::Custom2 => (100, 255, 220),
+        Element::Custom3 => (255, 255, 140),
+        Element::Custom4 => (230, 230, 230),
+    }
+}
+
+fn colour_blind_color(element: Element) -> (u8, u8, u8) {
+    const ORANGE: (u8, u8, u8) = (230, 159, 0);
+    const SKY_BLUE: (u8, u8, u8) = (86, 180, 233);
+    const GREEN: (u8, u8, u8) = (0, 158, 115);
+    const YELLOW: (u8, u8, u8) = (240, 228, 66);
+    const BLUE: (u8, u8, u8) = (0, 114, 178);
+    const VERMILLION: (u8, u8, u8) = (213, 94, 0);
+    const PURPLE: (u8, u8, u8) = (204, 121, 167);
+    const GREY: (u8, u8, u8) = (150, 150, 150);
+    match element {
+        Element::Air => (20, 20, 30),
+        Element::Sand => YELLOW,
+        Element::Rock => GREY,
+        Element::Water => BLUE,
+        // Lighter than plants, which have the same hue
+        Element::Acid => (140, 240, 200),
+        Element::Drain => (70, 70, 70),
+        Element::Wood => (140, 90, 40),
+        Element::Iron => (200, 200, 210),
+        Element::Rust => (150, 70, 20),
+        Element::Fire => VERMILLION,
+        Element::Ash => (230, 230, 230),
+        Element::Oil => PURPLE,
+        Element::Lava => ORANGE,
+        Element::Smoke => (100, 100, 110),
+        Element::Life => SKY_BLUE,
+        Element::Seed => (180, 220, 160),
+        Element::Plant => GREEN,
+        // Darker than fire, which has the same hue
+        Element::TNT => (120, 20, 50),
+        Element::Fuse => (120, 60, 30),
+        Element::Explosion => YELLOW,
+        Element::WaterSource => SKY_BLUE,
+        Element::AcidSource => (90, 200, 160),
+        Element::OilSource => (230, 170, 200),
+        Element::FireSource => (240, 130, 60),
+        Element::LavaSource => (250, 200, 90),
+        Element::Indestructible => (50, 50, 60),
+        Element::Custom1 => PURPLE,
+        Element::Custom2 => SKY_BLUE,
+        Element::Custom3 => YELLOW,
+        Element::Custom4 => (240, 240, 240),
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Theme::Classic => "classic",
+            Theme::HighContrast => "high_contrast",
+            Theme::ColourBlind => "colour_blind",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Theme {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Theme::ALL
+            .into_iter()
+            .find(|theme| theme.to_string() == s)
+            .ok_or(())
+    }
+}
+
+// Color at the given position in a gradient with evenly spaced colors
+pub fn gradient_color(gradient: &[(u8, u8, u8)], factor: u8, max: u8) -> (u8, u8, u8) {
+    if gradient.len() < 2 || max == 0 {
+        return gradient[gradient.len() - 1];
+    }
+    let position = factor.min(max) as u32 * (gradient.len() as u32 - 1);
+    let index = (position / max as u32) as usize;
+    if index >= gradient.len() - 1 {
+        return gradient[gradient.len() - 1];
+    }
+    let remainder = (position % max as u32) as u8;
+    crate::render::interpolate(&gradient[index + 1], &gradient[index], remainder, max)
+}
+
+// Brightness of a pattern at a position in the world, tiled in both directions
+pub fn pattern_value(pattern: Pattern, x: usize, y: usize) -> u8 {
+    let pixels = &pattern_images()[pattern as usize];
+    pixels[(x % PATTERN_SIZE) + (y % PATTERN_SIZE) * PATTERN_SIZE]
+}
+
+// Grayscale pixels of all patterns, in the order of the Pattern enum
+pub fn pattern_images() -> &'static [Vec<u8>] {
+    static PATTERNS: OnceLock<Vec<Vec<u8>>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            include_bytes!("../assets/patterns/brick.png").as_slice(),
+            include_bytes!("../assets/patterns/grain.png").as_slice(),
+        ]
+        .iter()
+        .map(|bytes| {
+            image::load_from_memory(bytes)
+                .unwrap()
+                .to_luma8()
+                .into_raw()
+        })
+        .collect()
+    })
+}
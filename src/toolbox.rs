@@ -1,4 +1,4 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
 
 use crate::{element::Element, pseudo_random::PseudoRandom, sandbox::SandBox};
 use bevy::prelude::Resource;
@@ -13,6 +13,38 @@ pub enum Tool {
     Fill,
 }
 
+impl Tool {
+    pub const ALL: [Tool; 5] = [
+        Tool::Pixel,
+        Tool::Circle,
+        Tool::Square,
+        Tool::Spray,
+        Tool::Fill,
+    ];
+}
+
+// Elements offered for editing, in the order they are shown
+pub const EDITOR_ELEMENTS: [Element; 18] = [
+    Element::Sand,
+    Element::Wood,
+    Element::Iron,
+    Element::Rock,
+    Element::Water,
+    Element::Acid,
+    Element::Oil,
+    Element::Lava,
+    Element::Fire,
+    Element::Life,
+    Element::Seed,
+    Element::TNT,
+    Element::Fuse,
+    Element::WaterSource,
+    Element::AcidSource,
+    Element::LavaSource,
+    Element::FireSource,
+    Element::Drain,
+];
+
 #[derive(Resource)]
 pub struct ToolBox {
     pub tool: Tool,
@@ -22,85 +54,137 @@ pub struct ToolBox {
 }
 
 impl ToolBox {
-    pub fn apply(&mut self, sandbox: &mut SandBox, x: usize, y: usize) {
-        let half_size = self.tool_size / 2;
-        let remainder = if half_size == 0 {
-            1
-        } else {
-            self.tool_size % half_size
-        };
-        let x1 = if x > half_size { x - half_size } else { 1 };
-        let x2 = if x + half_size + remainder < sandbox.width() {
-            x + half_size + remainder
-        } else {
-            sandbox.width()
-        };
-        let y1 = if y > half_size { y - half_size } else { 1 };
-        let y2 = if y + half_size + remainder < sandbox.height() {
-            y + half_size + remainder
-        } else {
-            sandbox.height()
+    // Select the next or previous of the given elements, wrapping around
+    pub fn cycle_element(&mut self, elements: &[Element], forward: bool) {
+        if elements.is_empty() {
+            return;
+        }
+        let next = match elements.iter().position(|element| *element == self.element) {
+            Some(index) if forward => (index + 1) % elements.len(),
+            Some(index) => (index + elements.len() - 1) % elements.len(),
+            None => 0,
         };
+        self.element = elements[next];
+    }
+
+    pub fn cycle_tool(&mut self) {
+        let index = Tool::ALL.iter().position(|tool| *tool == self.tool);
+        self.tool = Tool::ALL[index.map_or(0, |index| (index + 1) % Tool::ALL.len())];
+    }
+
+    pub fn apply(&mut self, sandbox: &mut SandBox, x: usize, y: usize) {
+        let (x1, y1, x2, y2) = self.brush_area(sandbox, x, y);
         match self.tool {
             Tool::Pixel => {
                 sandbox.set_element(x, y, self.element, self.random.next());
             }
-            Tool::Circle => {
-                let radius_sq = (half_size * half_size) as isize;
+            Tool::Circle | Tool::Square => {
                 for cy in y1..y2 {
                     for cx in x1..x2 {
-                        let dx = (cx as isize - x as isize).abs();
-                        let dy = (cy as isize - y as isize).abs();
-                        if dx * dx + dy * dy <= radius_sq {
+                        if self.in_brush(x, y, cx, cy) {
                             sandbox.set_element(cx, cy, self.element, self.random.next());
                         }
                     }
                 }
             }
-            Tool::Square => {
-                for cy in y1..y2 {
-                    for cx in x1..x2 {
-                        sandbox.set_element(cx, cy, self.element, self.random.next());
-                    }
-                }
-            }
             Tool::Spray => {
-                let radius_sq = (half_size * half_size) as isize;
+                if x1 >= x2 || y1 >= y2 {
+                    // Nothing to spray on the border
+                    return;
+                }
+                let half_size = self.tool_size / 2;
                 let count = if half_size > 3 { half_size / 3 } else { 1 };
                 for _ in 0..count {
                     let cx = x1 + self.random.next() as usize % (x2 - x1);
                     let cy = y1 + self.random.next() as usize % (y2 - y1);
-                    let dx = (cx as isize - x as isize).abs();
-                    let dy = (cy as isize - y as isize).abs();
-                    if dx * dx + dy * dy <= radius_sq {
+                    if self.in_brush(x, y, cx, cy) {
                         sandbox.set_element(cx, cy, self.element, self.random.next());
                     }
                 }
             }
             Tool::Fill => {
-                let mut checklist = Vec::new();
-                let element_to_replace = sandbox.get(x, y).element;
-                if element_to_replace == self.element
-                    || element_to_replace == Element::Indestructible
-                {
-                    return;
+                for (cx, cy) in self.fill_region(sandbox, x, y) {
+                    sandbox.set_element(cx, cy, self.element, self.random.next());
                 }
-                sandbox.set_element(x, y, self.element, self.random.next());
-                checklist.push((x, y));
-                while !checklist.is_empty() {
-                    let (x, y) = checklist.pop().unwrap();
-                    for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
-                        let neighbour_element = sandbox.get(nx, ny).element;
-                        if neighbour_element == element_to_replace
-                            && neighbour_element != Element::Indestructible
-                        {
-                            sandbox.set_element(nx, ny, self.element, self.random.next());
-                            checklist.push((nx, ny));
-                        }
-                    }
+            }
+        }
+    }
+
+    // Apply the tool with air instead of the element
+    pub fn erase(&mut self, sandbox: &mut SandBox, x: usize, y: usize) {
+        let element = self.element;
+        self.element = Element::Air;
+        self.apply(sandbox, x, y);
+        self.element = element;
+    }
+
+    // Area around a position covered by the brush, as (x1, y1, x2, y2) with exclusive ends
+    pub fn brush_area(
+        &self,
+        sandbox: &SandBox,
+        x: usize,
+        y: usize,
+    ) -> (usize, usize, usize, usize) {
+        if self.tool == Tool::Pixel {
+            return (x, y, x + 1, y + 1);
+        }
+        let half_size = self.tool_size / 2;
+        let remainder = if half_size == 0 {
+            1
+        } else {
+            self.tool_size % half_size
+        };
+        let x1 = if x > half_size { x - half_size } else { 1 };
+        let x2 = if x + half_size + remainder < sandbox.width() {
+            x + half_size + remainder
+        } else {
+            sandbox.width()
+        };
+        let y1 = if y > half_size { y - half_size } else { 1 };
+        let y2 = if y + half_size + remainder < sandbox.height() {
+            y + half_size + remainder
+        } else {
+            sandbox.height()
+        };
+        (x1, y1, x2, y2)
+    }
+
+    // Whether a cell in the brush area is part of the brush shape, the spray covers it randomly
+    pub fn in_brush(&self, x: usize, y: usize, cx: usize, cy: usize) -> bool {
+        match self.tool {
+            Tool::Pixel => cx == x && cy == y,
+            Tool::Circle | Tool::Spray => {
+                let half_size = self.tool_size / 2;
+                let radius_sq = (half_size * half_size) as isize;
+                let dx = (cx as isize - x as isize).abs();
+                let dy = (cy as isize - y as isize).abs();
+                dx * dx + dy * dy <= radius_sq
+            }
+            Tool::Square | Tool::Fill => true,
+        }
+    }
+
+    // Cells replaced by the fill tool at a position, in the order they are filled
+    pub fn fill_region(&self, sandbox: &SandBox, x: usize, y: usize) -> Vec<(usize, usize)> {
+        let element_to_replace = sandbox.get(x, y).element;
+        if element_to_replace == self.element || element_to_replace == Element::Indestructible {
+            return Vec::new();
+        }
+        let mut visited = vec![false; sandbox.width() * sandbox.height()];
+        let mut region = vec![(x, y)];
+        let mut checklist = vec![(x, y)];
+        visited[x + y * sandbox.width()] = true;
+        while let Some((x, y)) = checklist.pop() {
+            for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                let index = nx + ny * sandbox.width();
+                if !visited[index] && sandbox.get(nx, ny).element == element_to_replace {
+                    visited[index] = true;
+                    region.push((nx, ny));
+                    checklist.push((nx, ny));
                 }
             }
         }
+        region
     }
 }
 
@@ -120,3 +204,18 @@ impl fmt::Display for Tool {
         write!(f, "{:?}", self)
     }
 }
+
+impl FromStr for Tool {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Pixel" => Ok(Tool::Pixel),
+            "Circle" => Ok(Tool::Circle),
+            "Square" => Ok(Tool::Square),
+            "Spray" => Ok(Tool::Spray),
+            "Fill" => Ok(Tool::Fill),
+            _ => Err(()),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use bevy::{
+    input::touch::{TouchInput, TouchPhase},
+    prelude::*,
+    window::PrimaryWindow,
+};
+
+use crate::{camera::CameraZoom, pointer_input::PointerInputState};
+
+// Time a finger has to stay in place to erase instead of draw
+pub const LONG_PRESS_SECONDS: f32 = 0.5;
+// Distance in pixels a finger has to move to start drawing before the long press
+pub const DRAG_THRESHOLD: f32 = 10.0;
+
+// What the touches currently mean for the editor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gesture {
+    None,
+    Draw(Vec2),
+    Erase(Vec2),
+    // Two fingers, moving the view by the movement and scaling it by the zoom factor around the center
+    PanZoom {
+        center: Vec2,
+        movement: Vec2,
+        zoom: f32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum TouchMode {
+    #[default]
+    Idle,
+    // One finger down, waiting to see whether it moves, is held or is joined by another
+    Pending,
+    Draw,
+    Erase,
+    PanZoom,
+    // Ignore the remaining finger after a two finger gesture, until it is lifted
+    Finished,
+}
+
+// Recognizes gestures from touch events: one finger draws, a long press erases,
+// two fingers pan and pinch to zoom
+#[derive(Resource, Default)]
+pub struct TouchGestures {
+    // Active touches in the order they started
+    touches: Vec<(u64, Vec2)>,
+    mode: TouchMode,
+    start: Vec2,
+    held_seconds: f32,
+    // Center and distance between the two fingers in the previous update
+    previous_pair: Option<(Vec2, f32)>,
+    // A short tap that still has to be drawn
+    tap: Option<Vec2>,
+}
+
+impl TouchGestures {
+    pub fn is_active(&self) -> bool {
+        self.mode != TouchMode::Idle || self.tap.is_some()
+    }
+
+    pub fn handle(&mut self, event: &TouchInput) {
+        match event.phase {
+            TouchPhase::Started => {
+                self.touches.push((event.id, event.position));
+                if self.touches.len() == 1 && self.mode == TouchMode::Idle {
+                    self.mode = TouchMode::Pending;
+                    self.start = event.position;
+                    self.held_seconds = 0.0;
+                } else if self.touches.len() >= 2 {
+                    self.mode = TouchMode::PanZoom;
+                    self.previous_pair = None;
+                }
+            }
+            TouchPhase::Moved => {
+                if let Some(touch) = self.touches.iter_mut().find(|(id, _)| *id == event.id) {
+                    touch.1 = event.position;
+                }
+                if self.mode == TouchMode::Pending
+                    && event.position.distance(self.start) > DRAG_THRESHOLD
+                {
+                    self.mode = TouchMode::Draw;
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.touches.retain(|(id, _)| *id != event.id);
+                if self.mode == TouchMode::Pending && event.phase == TouchPhase::Ended {
+                    self.tap = Some(event.position);
+                }
+                if self.touches.is_empty() {
+                    self.mode = TouchMode::Idle;
+                } else if self.mode == TouchMode::PanZoom && self.touches.len() < 2 {
+                    self.mode = TouchMode::Finished;
+                }
+            }
+        }
+    }
+
+    // Gesture for this frame, after handling the touch events of the frame
+    pub fn update(&mut self, delta_seconds: f32) -> Gesture {
+        if let Some(tap) = self.tap.take() {
+            return Gesture::Draw(tap);
+        }
+        if self.mode == TouchMode::Pending {
+            self.held_seconds += delta_seconds;
+            if self.held_seconds >= LONG_PRESS_SECONDS {
+                self.mode = TouchMode::Erase;
+            }
+        }
+        match self.mode {
+            TouchMode::Draw => Gesture::Draw(self.touches[0].1),
+            TouchMode::Erase => Gesture::Erase(self.touches[0].1),
+            TouchMode::PanZoom => {
+                let (first, second) = (self.touches[0].1, self.touches[1].1);
+                let center = (first + second) / 2.0;
+                let distance = first.distance(second).max(1.0);
+                let (movement, zoom) = match self.previous_pair {
+                    Some((previous_center, previous_distance)) => {
+                        (previous_center - center, previous_distance / distance)
+                    }
+                    None => (Vec2::ZERO, 1.0),
+                };
+                self.previous_pair = Some((center, distance));
+                Gesture::PanZoom {
+                    center,
+                    movement,
+                    zoom,
+                }
+            }
+            TouchMode::Idle | TouchMode::Pending | TouchMode::Finished => Gesture::None,
+        }
+    }
+
+    // Position of the first finger, if any
+    pub fn position(&self) -> Option<Vec2> {
+        self.touches.first().map(|(_, position)| *position)
+    }
+}
+
+// System turning touch gestures into pointer input and camera movement
+pub(crate) fn touch_system(
+    mut touch_events: EventReader<TouchInput>,
+    time: Res<Time>,
+    mut gestures: ResMut<TouchGestures>,
+    mut pointer: ResMut<PointerInputState>,
+    mut camera_zoom: ResMut<CameraZoom>,
+    mut camera: Query<&mut Transform, With<Camera>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+) {
+    let mut touched = false;
+    for event in touch_events.iter() {
+        gestures.handle(event);
+        touched = true;
+    }
+    if !touched && !gestures.is_active() {
+        // Leave the pointer to the mouse
+        return;
+    }
+
+    // Touches start at the top of the window, the cursor at the bottom
+    let height = window.get_single().map_or(0.0, |window| window.height());
+    let to_window = |position: Vec2| Vec2::new(position.x, height - position.y);
+
+    let gesture = gestures.update(time.delta_seconds());
+    pointer.left_button_down = false;
+    pointer.middle_button_down = false;
+    pointer.right_button_down = false;
+    if let Some(position) = gestures.position() {
+        pointer.position = to_window(position);
+    }
+    match gesture {
+        Gesture::Draw(position) => {
+            pointer.position = to_window(position);
+            pointer.left_button_down = true;
+        }
+        Gesture::Erase(position) => {
+            pointer.position = to_window(position);
+            pointer.right_button_down = true;
+        }
+        Gesture::PanZoom {
+            center,
+            movement,
+            zoom,
+        } => {
+            pointer.position = to_window(center);
+            if let Ok(mut transform) = camera.get_single_mut() {
+                transform.translation.x += movement.x * transform.scale.x;
+                transform.translation.y -= movement.y * transform.scale.y;
+            }
+            if zoom != 1.0 {
+                camera_zoom.zoom_by(zoom, Some(pointer.position));
+            }
+        }
+        Gesture::None => {}
+    }
+}
@@ -0,0 +1,616 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    ops::Range,
+    path::PathBuf,
+};
+
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    utils::Instant,
+    window::PrimaryWindow,
+};
+
+use crate::{
+    cell::Cell,
+    element::Element,
+    render::{render_cells, RenderState},
+    sandbox::SandBox,
+    settings::Settings,
+    simulation::{frame_steps, measure_speed, update_cells, Simulation},
+    theme::Theme,
+    toolbox::{Tool, ToolBox},
+};
+
+// Width and height in cells of the chunks the world is made of
+pub const WORLD_CHUNK_SIZE: usize = 64;
+// Steps without changes after which a chunk is no longer simulated
+const IDLE_STEPS: u32 = 16;
+// Distance in chunks outside the view within which chunks stay in memory
+const KEEP_DISTANCE: i32 = 2;
+// Width and height of the area around the cursor filled by the fill tool at most
+const FILL_SIZE: usize = 256;
+
+const AIR: Cell = Cell {
+    element: Element::Air,
+    variant: 0,
+    strength: 0,
+    visited: false,
+};
+
+// Position of a chunk in chunks from the origin, cell (0, 0) is the top left cell of chunk (0, 0)
+pub type ChunkPosition = (i32, i32);
+
+struct Chunk {
+    sandbox: SandBox,
+    // Steps since a cell in this chunk or on the edge of a chunk next to it changed
+    idle_steps: u32,
+    // Step in which the cells were last updated, the visited state of older steps is reset
+    step: u64,
+}
+
+// A world without edges, made of chunks that are only allocated when something is in them.
+// Chunks far from the view that are not changing are stored on disk and read back when needed.
+#[derive(Resource)]
+pub struct ChunkWorld {
+    chunks: HashMap<ChunkPosition, Chunk>,
+    // Chunks stored on disk instead of in memory
+    stored: HashSet<ChunkPosition>,
+    // Directory with the stored chunks, all chunks stay in memory without one
+    directory: Option<PathBuf>,
+    step: u64,
+    // A chunk with the cells around it, in which chunks are simulated
+    window: SandBox,
+    // Cells of the window before simulating, to find the ones that changed
+    original: Vec<Cell>,
+}
+
+impl ChunkWorld {
+    pub fn new(directory: Option<PathBuf>) -> Self {
+        // Continue with the chunks stored in an earlier run
+        let stored = directory
+            .iter()
+            .filter_map(|directory| fs::read_dir(directory).ok())
+            .flatten()
+            .filter_map(|entry| parse_chunk_name(&entry.ok()?.file_name().to_string_lossy()))
+            .collect();
+        let window_size = WORLD_CHUNK_SIZE + 2;
+        ChunkWorld {
+            chunks: HashMap::new(),
+            stored,
+            directory,
+            step: 0,
+            window: SandBox::new(window_size, window_size),
+            original: vec![AIR; window_size * window_size],
+        }
+    }
+
+    // Cell at a position, chunks that are stored on disk read as air
+    pub fn get(&self, x: i64, y: i64) -> Cell {
+        let (position, x, y) = chunk_of(x, y);
+        self.chunks
+            .get(&position)
+            .map_or(AIR, |chunk| chunk.sandbox.get(x, y).clone())
+    }
+
+    pub fn set_element(&mut self, x: i64, y: i64, element: Element, random: u32) {
+        let (position, x, y) = chunk_of(x, y);
+        self.load_chunk(position);
+        if element == Element::Air && !self.chunks.contains_key(&position) {
+            return;
+        }
+        allocate(&mut self.chunks, self.step, position).set_element(x, y, element, random);
+        self.wake(position, true);
+    }
+
+    pub fn set_cell(&mut self, x: i64, y: i64, cell: Cell) {
+        let (position, x, y) = chunk_of(x, y);
+        self.load_chunk(position);
+        if cell.element == Element::Air && !self.chunks.contains_key(&position) {
+            return;
+        }
+        allocate(&mut self.chunks, self.step, position).set_cell(x, y, cell);
+        self.wake(position, true);
+    }
+
+    // Cells of a chunk in memory
+    pub fn chunk(&self, position: ChunkPosition) -> Option<&SandBox> {
+        self.chunks.get(&position).map(|chunk| &chunk.sandbox)
+    }
+
+    // Positions of the chunks in memory
+    pub fn chunk_positions(&self) -> impl Iterator<Item = ChunkPosition> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn is_stored(&self, position: ChunkPosition) -> bool {
+        self.stored.contains(&position)
+    }
+
+    pub fn clear_dirty_chunks(&mut self) {
+        for chunk in self.chunks.values_mut() {
+            chunk.sandbox.clear_dirty_chunks();
+        }
+    }
+
+    // Simulate a step of all chunks that are changing, a chunk and the cells around it at a time
+    pub fn step(&mut self, simulation: &mut Simulation) {
+        if !simulation.running && !simulation.step {
+            return;
+        }
+        simulation.step = false;
+        simulation.step_count += 1;
+        self.step += 1;
+        let forward = self.step % 2 == 1;
+        if let Some(mass_balance) = simulation.mass_balance.as_mut() {
+            mass_balance.start_step();
+        }
+
+        // From the bottom up and in the order of the cells within a chunk
+        let mut positions: Vec<ChunkPosition> = self
+            .chunks
+            .iter()
+            .filter(|(_, chunk)| chunk.idle_steps < IDLE_STEPS)
+            .map(|(position, _)| *position)
+            .collect();
+        positions.sort_by_key(|&(x, y)| (-y, if forward { x } else { -x }));
+        // Chunks with changed cells, and whether the changes were on the edge
+        let mut changed = HashMap::new();
+        for position in positions {
+            self.simulate_chunk(simulation, position, forward, &mut changed);
+        }
+
+        for chunk in self.chunks.values_mut() {
+            chunk.idle_steps = chunk.idle_steps.saturating_add(1);
+        }
+        for (position, on_edge) in changed {
+            self.wake(position, on_edge);
+        }
+    }
+
+    // Simulate the cells of a chunk with the cells around it, so cells can move between chunks
+    fn simulate_chunk(
+        &mut self,
+        simulation: &mut Simulation,
+        position: ChunkPosition,
+        forward: bool,
+        changed: &mut HashMap<ChunkPosition, bool>,
+    ) {
+        let window_size = self.window.width();
+        for (neighbour, xs, ys) in window_sections(position) {
+            self.load_chunk(neighbour);
+            let chunk = synced_chunk(&mut self.chunks, self.step, neighbour);
+            for y in ys {
+                for x in xs.clone() {
+                    let (cell, visited) = chunk.as_ref().map_or((&AIR, false), |chunk| {
+                        let (x, y) = (chunk_coordinate(x), chunk_coordinate(y));
+                        (chunk.get(x, y), chunk.is_visited(x, y))
+                    });
+                    self.window.load_cell(x, y, cell, visited);
+                    self.original[x + y * window_size] = self.window.get(x, y).clone();
+                }
+            }
+        }
+
+        // Only the updates are accounted for, not the loading of the cells
+        let account = simulation.mass_balance.is_some();
+        self.window.record_changes(account);
+        update_cells(simulation, &mut self.window, forward);
+        self.window.record_changes(false);
+
+        for (neighbour, xs, ys) in window_sections(position) {
+            let differs =
+                |x: usize, y: usize| self.window.get(x, y) != &self.original[x + y * window_size];
+            // Nothing to keep in an empty chunk
+            let needed = self.chunks.contains_key(&neighbour)
+                || ys.clone().any(|y| {
+                    xs.clone()
+                        .any(|x| differs(x, y) && self.window.get(x, y).element != Element::Air)
+                });
+            if !needed {
+                continue;
+            }
+            let chunk = allocate(&mut self.chunks, self.step, neighbour);
+            for y in ys {
+                for x in xs.clone() {
+                    let cell = self.window.get(x, y);
+                    let original = &self.original[x + y * window_size];
+                    if cell == original {
+                        continue;
+                    }
+                    let (chunk_x, chunk_y) = (chunk_coordinate(x), chunk_coordinate(y));
+                    if contents(cell) != contents(original) {
+                        // Only changes on the edge of a chunk affect the chunks around it
+                        let edge = WORLD_CHUNK_SIZE - 1;
+                        let on_edge =
+                            chunk_x == 0 || chunk_y == 0 || chunk_x == edge || chunk_y == edge;
+                        let woken = changed.entry(neighbour).or_insert(false);
+                        *woken |= on_edge;
+                    }
+                    chunk.load_cell(chunk_x, chunk_y, cell, self.window.is_visited(x, y));
+                }
+            }
+        }
+    }
+
+    // Apply a tool at a cell on a copy of the cells around it, so the fill tool fills at most an
+    // area of FILL_SIZE around the cell
+    pub fn apply_tool(&mut self, toolbox: &mut ToolBox, x: i64, y: i64, erase: bool) {
+        let size = match toolbox.tool {
+            Tool::Fill => FILL_SIZE,
+            _ => toolbox.tool_size + 2,
+        };
+        // The area has a border around the copied cells, like any sandbox
+        let (left, top) = (x - size as i64 / 2 - 1, y - size as i64 / 2 - 1);
+        let mut area = SandBox::new(size + 2, size + 2);
+        for area_y in 1..size + 1 {
+            for area_x in 1..size + 1 {
+                let (position, cell_x, cell_y) =
+                    chunk_of(left + area_x as i64, top + area_y as i64);
+                self.load_chunk(position);
+                let (cell, visited) = self.chunk(position).map_or((&AIR, false), |chunk| {
+                    (chunk.get(cell_x, cell_y), chunk.is_visited(cell_x, cell_y))
+                });
+                area.load_cell(area_x, area_y, &cell.clone(), visited);
+            }
+        }
+        let original = area.clone();
+        let (area_x, area_y) = ((x - left) as usize, (y - top) as usize);
+        if erase {
+            toolbox.erase(&mut area, area_x, area_y);
+        } else {
+            toolbox.apply(&mut area, area_x, area_y);
+        }
+        for area_y in 1..size + 1 {
+            for area_x in 1..size + 1 {
+                let cell = area.get(area_x, area_y);
+                if contents(cell) != contents(original.get(area_x, area_y)) {
+                    self.set_cell(left + area_x as i64, top + area_y as i64, cell.clone());
+                }
+            }
+        }
+    }
+
+    // Read the stored chunks in an area back into memory, given as the first and last chunk
+    pub fn load_area(&mut self, min: ChunkPosition, max: ChunkPosition) {
+        for y in min.1..=max.1 {
+            for x in min.0..=max.0 {
+                self.load_chunk((x, y));
+            }
+        }
+    }
+
+    // Store the chunks that are not changing and are far outside an area, and forget the empty ones
+    pub fn store_far_chunks(&mut self, min: ChunkPosition, max: ChunkPosition) {
+        let far: Vec<ChunkPosition> = self
+            .chunks
+            .iter()
+            .filter(|((x, y), chunk)| {
+                chunk.idle_steps >= IDLE_STEPS
+                    && (*x < min.0 - KEEP_DISTANCE
+                        || *x > max.0 + KEEP_DISTANCE
+                        || *y < min.1 - KEEP_DISTANCE
+                        || *y > max.1 + KEEP_DISTANCE)
+            })
+            .map(|(position, _)| *position)
+            .collect();
+        for position in far {
+            self.store_chunk(position);
+        }
+    }
+
+    // Store all chunks, e.g. before exiting
+    pub fn store_all(&mut self) {
+        let positions: Vec<ChunkPosition> = self.chunks.keys().copied().collect();
+        for position in positions {
+            self.store_chunk(position);
+        }
+    }
+
+    fn store_chunk(&mut self, position: ChunkPosition) {
+        let Some(chunk) = self.chunks.get(&position) else {
+            return;
+        };
+        let path = self
+            .directory
+            .as_ref()
+            .map(|directory| directory.join(chunk_name(position)));
+        if chunk.sandbox.count(Element::Air) == WORLD_CHUNK_SIZE * WORLD_CHUNK_SIZE {
+            self.chunks.remove(&position);
+            if let Some(path) = path {
+                let _ = fs::remove_file(path);
+            }
+            return;
+        }
+        let Some(path) = path else {
+            // Nowhere to store it, so keep it in memory
+            return;
+        };
+        if let Some(directory) = path.parent() {
+            let _ = fs::create_dir_all(directory);
+        }
+        if let Err(error) = fs::write(&path, chunk.sandbox.to_bytes()) {
+            warn!("Could not write {}: {}", path.display(), error);
+            return;
+        }
+        self.chunks.remove(&position);
+        self.stored.insert(position);
+    }
+
+    fn load_chunk(&mut self, position: ChunkPosition) {
+        if !self.stored.remove(&position) {
+            return;
+        }
+        let Some(directory) = self.directory.as_ref() else {
+            return;
+        };
+        let path = directory.join(chunk_name(position));
+        let sandbox = fs::read(&path)
+            .ok()
+            .and_then(|bytes| SandBox::from_bytes(&bytes))
+            .filter(|sandbox| {
+                sandbox.width() == WORLD_CHUNK_SIZE && sandbox.height() == WORLD_CHUNK_SIZE
+            });
+        let Some(sandbox) = sandbox else {
+            warn!("Could not read {}", path.display());
+            return;
+        };
+        self.chunks.insert(
+            position,
+            Chunk {
+                sandbox,
+                idle_steps: 0,
+                step: self.step,
+            },
+        );
+    }
+
+    // Simulate a chunk again, and the chunks next to it for changes that may affect them
+    fn wake(&mut self, position: ChunkPosition, neighbours: bool) {
+        let range = if neighbours { 1 } else { 0 };
+        for y in position.1 - range..=position.1 + range {
+            for x in position.0 - range..=position.0 + range {
+                if let Some(chunk) = self.chunks.get_mut(&(x, y)) {
+                    chunk.idle_steps = 0;
+                }
+            }
+        }
+    }
+}
+
+// Chunk in memory, with the visited state of earlier steps reset
+fn synced_chunk(
+    chunks: &mut HashMap<ChunkPosition, Chunk>,
+    step: u64,
+    position: ChunkPosition,
+) -> Option<&mut SandBox> {
+    let chunk = chunks.get_mut(&position)?;
+    if chunk.step != step {
+        chunk.step = step;
+        chunk.sandbox.toggle_visited_state();
+    }
+    Some(&mut chunk.sandbox)
+}
+
+// Chunk in memory, created when there is none yet
+fn allocate(
+    chunks: &mut HashMap<ChunkPosition, Chunk>,
+    step: u64,
+    position: ChunkPosition,
+) -> &mut SandBox {
+    chunks.entry(position).or_insert_with(|| Chunk {
+        sandbox: SandBox::empty(WORLD_CHUNK_SIZE, WORLD_CHUNK_SIZE),
+        idle_steps: 0,
+        step,
+    });
+    synced_chunk(chunks, step, position).unwrap()
+}
+
+// Chunk containing a cell, and the position of the cell in it
+fn chunk_of(x: i64, y: i64) -> (ChunkPosition, usize, usize) {
+    let size = WORLD_CHUNK_SIZE as i64;
+    (
+        (x.div_euclid(size) as i32, y.div_euclid(size) as i32),
+        x.rem_euclid(size) as usize,
+        y.rem_euclid(size) as usize,
+    )
+}
+
+// Parts of the window around a chunk, as the chunk they are in and the columns and rows they cover
+fn window_sections(position: ChunkPosition) -> Vec<(ChunkPosition, Range<usize>, Range<usize>)> {
+    let ranges = [
+        (-1, 0..1),
+        (0, 1..WORLD_CHUNK_SIZE + 1),
+        (1, WORLD_CHUNK_SIZE + 1..WORLD_CHUNK_SIZE + 2),
+    ];
+    let mut sections = Vec::with_capacity(9);
+    for (dy, ys) in ranges.iter() {
+        for (dx, xs) in ranges.iter() {
+            sections.push(((position.0 + dx, position.1 + dy), xs.clone(), ys.clone()));
+        }
+    }
+    sections
+}
+
+// Column or row within its chunk of a column or row of the window
+fn chunk_coordinate(window: usize) -> usize {
+    (window + WORLD_CHUNK_SIZE - 1) % WORLD_CHUNK_SIZE
+}
+
+fn contents(cell: &Cell) -> (Element, u8, u8) {
+    (cell.element, cell.variant, cell.strength)
+}
+
+fn chunk_name(position: ChunkPosition) -> String {
+    format!("{}_{}.chunk", position.0, position.1)
+}
+
+fn parse_chunk_name(name: &str) -> Option<ChunkPosition> {
+    let (x, y) = name.strip_suffix(".chunk")?.split_once('_')?;
+    Some((x.parse().ok()?, y.parse().ok()?))
+}
+
+/// Replaces the sandbox by an endless world made of chunks, given with `--world`
+pub struct ChunkWorldPlugin;
+
+impl Plugin for ChunkWorldPlugin {
+    fn build(&self, app: &mut App) {
+        if !env::args().any(|arg| arg == "--world") {
+            return;
+        }
+        app.insert_resource(ChunkWorld::new(world_directory()))
+            .init_resource::<ChunkSprites>()
+            .add_system(world_simulation_system)
+            .add_system(world_view_system.after(world_simulation_system))
+            .add_system(world_render_system.after(world_view_system))
+            .add_system(store_world.in_base_set(CoreSet::Last));
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn world_directory() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("falling-rust").join("world"))
+}
+
+#[cfg(target_family = "wasm")]
+fn world_directory() -> Option<PathBuf> {
+    None
+}
+
+struct ChunkSprite {
+    entity: Entity,
+    image: Handle<Image>,
+    rendered: bool,
+}
+
+// Sprites showing the chunks in view
+#[derive(Resource)]
+struct ChunkSprites {
+    sprites: HashMap<ChunkPosition, ChunkSprite>,
+    theme: Theme,
+    // Shown where there is no chunk
+    empty: SandBox,
+}
+
+impl Default for ChunkSprites {
+    fn default() -> Self {
+        ChunkSprites {
+            sprites: HashMap::new(),
+            theme: Theme::default(),
+            empty: SandBox::empty(WORLD_CHUNK_SIZE, WORLD_CHUNK_SIZE),
+        }
+    }
+}
+
+fn world_simulation_system(
+    mut world: ResMut<ChunkWorld>,
+    mut simulation: ResMut<Simulation>,
+    time: Res<Time>,
+) {
+    let simulation = simulation.as_mut();
+    let steps = frame_steps(simulation, &time);
+    let start = Instant::now();
+    for _ in 0..steps {
+        world.step(simulation);
+    }
+    simulation.frame_time_ms = (Instant::now() - start).as_millis();
+    measure_speed(simulation, steps, &time);
+}
+
+// System keeping the chunks in view in memory with a sprite each, and storing the far ones
+fn world_view_system(
+    mut commands: Commands,
+    mut world: ResMut<ChunkWorld>,
+    mut sprites: ResMut<ChunkSprites>,
+    mut images: ResMut<Assets<Image>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<&Transform, With<Camera>>,
+) {
+    let (Ok(window), Ok(transform)) = (window.get_single(), camera.get_single()) else {
+        return;
+    };
+    // Cell (x, y) is shown at (x, -y) in the world of the camera
+    let half_size = Vec2::new(window.width(), window.height()) / 2.0 * transform.scale.x;
+    let center = Vec2::new(transform.translation.x, -transform.translation.y);
+    let chunk = |value: f32| (value / WORLD_CHUNK_SIZE as f32).floor() as i32;
+    let min = (chunk(center.x - half_size.x), chunk(center.y - half_size.y));
+    let max = (chunk(center.x + half_size.x), chunk(center.y + half_size.y));
+    world.load_area(min, max);
+    world.store_far_chunks(min, max);
+
+    sprites.sprites.retain(|(x, y), sprite| {
+        let visible = (min.0..=max.0).contains(x) && (min.1..=max.1).contains(y);
+        if !visible {
+            commands.entity(sprite.entity).despawn();
+            images.remove(&sprite.image);
+        }
+        visible
+    });
+    for y in min.1..=max.1 {
+        for x in min.0..=max.0 {
+            sprites.sprites.entry((x, y)).or_insert_with(|| {
+                let image = images.add(Image::new_fill(
+                    Extent3d {
+                        width: WORLD_CHUNK_SIZE as u32,
+                        height: WORLD_CHUNK_SIZE as u32,
+                        depth_or_array_layers: 1,
+                    },
+                    TextureDimension::D2,
+                    &[0, 0, 0, 255],
+                    TextureFormat::Rgba8UnormSrgb,
+                ));
+                let size = WORLD_CHUNK_SIZE as f32;
+                let entity = commands
+                    .spawn(SpriteBundle {
+                        texture: image.clone(),
+                        transform: Transform::from_xyz(
+                            (x as f32 + 0.5) * size,
+                            -(y as f32 + 0.5) * size,
+                            0.0,
+                        ),
+                        ..Default::default()
+                    })
+                    .id();
+                ChunkSprite {
+                    entity,
+                    image,
+                    rendered: false,
+                }
+            });
+        }
+    }
+}
+
+// System coloring the sprites of the chunks that changed
+fn world_render_system(
+    mut world: ResMut<ChunkWorld>,
+    mut sprites: ResMut<ChunkSprites>,
+    mut images: ResMut<Assets<Image>>,
+    mut render_state: ResMut<RenderState>,
+    settings: Res<Settings>,
+) {
+    let sprites = sprites.as_mut();
+    let theme = settings.theme;
+    let theme_changed = sprites.theme != theme;
+    sprites.theme = theme;
+    for (position, sprite) in sprites.sprites.iter_mut() {
+        let chunk = match world.chunk(*position) {
+            Some(chunk) if !sprite.rendered || theme_changed || chunk.is_dirty() => chunk,
+            None if !sprite.rendered || theme_changed => &sprites.empty,
+            _ => continue,
+        };
+        if let Some(image) = images.get_mut(&sprite.image) {
+            render_cells(chunk, theme, &mut render_state.random, &mut image.data);
+            sprite.rendered = true;
+        }
+    }
+    world.clear_dirty_chunks();
+}
+
+fn store_world(mut world: ResMut<ChunkWorld>, mut app_exit_events: EventReader<AppExit>) {
+    if app_exit_events.iter().count() > 0 {
+        world.store_all();
+    }
+}
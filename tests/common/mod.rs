@@ -0,0 +1,32 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use falling_rust::element::Element;
+use falling_rust::neighbourhood::Neighbourhood;
+use falling_rust::simulation::CustomUpdate;
+
+// Custom element that counts its updates and wanders around
+pub struct Wanderer {
+    pub updates: Arc<AtomicUsize>,
+}
+
+impl CustomUpdate for Wanderer {
+    fn defines(&self, element: Element) -> bool {
+        element == Element::Custom1
+    }
+
+    fn update(&self, _element: Element, neighbourhood: &mut Neighbourhood) {
+        self.updates.fetch_add(1, Ordering::Relaxed);
+        let (dx, dy) = match neighbourhood.random() % 4 {
+            0 => (-1, 0),
+            1 => (1, 0),
+            2 => (0, -1),
+            _ => (0, 1),
+        };
+        if neighbourhood.get(dx, dy).map(|cell| cell.element) == Some(Element::Air) {
+            neighbourhood.swap(dx, dy);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use falling_rust::conservation::MassBalance;
+use falling_rust::element::{Element, ELEMENT_COUNT};
+use falling_rust::sandbox::SandBox;
+use falling_rust::simulation::{simulation_step, Simulation};
+
+fn mass(sandbox: &SandBox) -> i64 {
+    (sandbox.width() * sandbox.height() - sandbox.count(Element::Air)) as i64
+}
+
+fn all_elements() -> impl Iterator<Item = Element> {
+    (0..ELEMENT_COUNT as u8).filter_map(|value| Element::try_from(value).ok())
+}
+
+// A bit of everything that creates or destroys mass
+fn reactions() -> SandBox {
+    let size = 48;
+    let mut sandbox = SandBox::new(size, size);
+    for x in 1..size - 1 {
+        sandbox.set_element(x, size - 2, Element::Drain, 0);
+        sandbox.set_element(x, 20, Element::Wood, 0);
+        sandbox.set_element(x, 12, Element::Acid, 0);
+        sandbox.set_element(x, 13, Element::Rock, 0);
+    }
+    sandbox.set_element(10, size - 6, Element::WaterSource, 0);
+    sandbox.set_element(30, 21, Element::Fire, 0);
+    sandbox.set_element(20, 30, Element::TNT, 0);
+    sandbox.set_element(20, 31, Element::Fire, 0);
+    for (x, y) in [(40, 30), (41, 30), (42, 30), (41, 29)] {
+        sandbox.set_element(x, y, Element::Life, 0);
+    }
+    sandbox
+}
+
+#[test]
+fn reports_mass_created_and_destroyed_per_reaction() {
+    let mut simulation = Simulation::new();
+    simulation.mass_balance = Some(MassBalance::new(false));
+    let mut sandbox = reactions();
+    let start = mass(&sandbox);
+    for _ in 0..100 {
+        let before = mass(&sandbox);
+        let counts = *sandbox.element_counts();
+        simulation_step(&mut simulation, &mut sandbox);
+        let mass_balance = simulation.mass_balance.as_ref().unwrap();
+        let created: i64 = all_elements()
+            .map(|element| mass_balance.created(element) as i64)
+            .sum();
+        assert_eq!(mass(&sandbox) - before, created);
+        for element in all_elements() {
+            assert_eq!(
+                mass_balance.element_change(element),
+                sandbox.count(element) as i32 - counts[element as usize] as i32
+            );
+        }
+    }
+    let mass_balance = simulation.mass_balance.as_ref().unwrap();
+    let total: i64 = all_elements()
+        .map(|element| mass_balance.total_created(element))
+        .sum();
+    assert_eq!(mass(&sandbox) - start, total);
+    assert!(mass_balance.total_created(Element::WaterSource) > 0);
+    assert!(mass_balance.total_created(Element::Drain) < 0);
+}
+
+#[test]
+fn prevents_creating_and_destroying_mass() {
+    let mut simulation = Simulation::new();
+    simulation.mass_balance = Some(MassBalance::new(true));
+    let mut sandbox = reactions();
+    let start = mass(&sandbox);
+    let mut prevented = 0;
+    for _ in 0..100 {
+        simulation_step(&mut simulation, &mut sandbox);
+        assert_eq!(mass(&sandbox), start);
+        let mass_balance = simulation.mass_balance.as_ref().unwrap();
+        prevented += all_elements()
+            .map(|element| mass_balance.prevented(element))
+            .sum::<u32>();
+    }
+    assert!(prevented > 0);
+    // Reactions that keep the mass still happen
+    assert_eq!(sandbox.count(Element::Water), 0);
+    assert!(sandbox.count(Element::Wood) < 46);
+}
+
+#[test]
+fn moving_elements_keep_their_mass() {
+    let mut simulation = Simulation::new();
+    simulation.mass_balance = Some(MassBalance::new(false));
+    let mut sandbox = SandBox::new(32, 32);
+    for x in 5..25 {
+        sandbox.set_element(x, 5, Element::Sand, 0);
+        sandbox.set_element(x, 10, Element::Water, 0);
+        sandbox.set_element(x, 11, Element::Oil, 0);
+    }
+    for _ in 0..50 {
+        simulation_step(&mut simulation, &mut sandbox);
+        let mass_balance = simulation.mass_balance.as_ref().unwrap();
+        for element in all_elements() {
+            assert_eq!(mass_balance.created(element), 0);
+            assert_eq!(mass_balance.element_change(element), 0);
+        }
+    }
+}
@@ -0,0 +1,358 @@
+// Regression tests comparing the outcome of small scenarios with checked-in golden files in
+// tests/golden. After an intended change in behaviour, update them with:
+//   BLESS=1 cargo test --test golden
+
+use std::{env, fs, path::PathBuf};
+
+use falling_rust::element::Element;
+use falling_rust::neighbourhood::Neighbourhood;
+use falling_rust::sandbox::SandBox;
+use falling_rust::simulation::{simulation_step, CustomUpdate, Simulation};
+use std::sync::Arc;
+
+// Character for each element in scenarios and golden files, indexed by element
+const ELEMENT_CHARS: &str = ".s#wadWIRfhol~L,pT-*12345=ABCD";
+
+fn element_char(element: Element) -> char {
+    ELEMENT_CHARS.chars().nth(element as usize).unwrap()
+}
+
+fn char_element(c: char) -> Element {
+    let index = ELEMENT_CHARS
+        .chars()
+        .position(|other| other == c)
+        .unwrap_or_else(|| panic!("no element for '{}'", c));
+    Element::try_from(index as u8).unwrap()
+}
+
+// Build a sandbox from rows of element characters, the border is always indestructible
+fn parse(rows: &[&str]) -> SandBox {
+    let mut sandbox = SandBox::new(rows[0].len() + 2, rows.len() + 2);
+    for (y, row) in rows.iter().enumerate() {
+        assert_eq!(row.len(), rows[0].len(), "rows differ in length");
+        for (x, c) in row.chars().enumerate() {
+            sandbox.set_element(x + 1, y + 1, char_element(c), (x * 31 + y * 17) as u32);
+        }
+    }
+    sandbox
+}
+
+fn dump(name: &str, steps: usize, simulation: &Simulation, sandbox: &SandBox) -> String {
+    let mut text = format!(
+        "{} after {} steps\nhash {:016x}\n",
+        name,
+        steps,
+        simulation.state_hash(sandbox)
+    );
+    for y in 0..sandbox.height() {
+        for x in 0..sandbox.width() {
+            text.push(element_char(sandbox.get(x, y).element));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn check_scenario(name: &str, rows: &[&str], steps: usize, simulation: Simulation) {
+    let mut simulation = simulation;
+    let mut sandbox = parse(rows);
+    for _ in 0..steps {
+        simulation_step(&mut simulation, &mut sandbox);
+    }
+    let actual = dump(name, steps, &simulation, &sandbox);
+
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name]
+        .iter()
+        .collect::<PathBuf>()
+        .with_extension("txt");
+    if env::var_os("BLESS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "missing {}, create it with BLESS=1 cargo test --test golden",
+            path.display()
+        )
+    });
+    if expected != actual {
+        panic!(
+            "{} differs from {}, if intended update it with BLESS=1 cargo test --test golden\n\
+             expected:\n{}\nactual:\n{}",
+            name,
+            path.display(),
+            expected,
+            actual
+        );
+    }
+}
+
+fn check(name: &str, rows: &[&str], steps: usize) {
+    check_scenario(name, rows, steps, Simulation::new());
+}
+
+#[test]
+fn powder() {
+    check(
+        "powder",
+        &[
+            "....ssssss....",
+            "....ssssss....",
+            "..............",
+            "......##......",
+            "..............",
+            "..............",
+            "..............",
+        ],
+        40,
+    );
+}
+
+#[test]
+fn liquid() {
+    check(
+        "liquid",
+        &[
+            "wwww.....oooo",
+            "wwww.....oooo",
+            "wwww.....oooo",
+            ".............",
+            "......#......",
+            ".............",
+        ],
+        60,
+    );
+}
+
+#[test]
+fn water() {
+    // Water reacting with acid, lava and fire
+    check(
+        "water",
+        &[
+            "..www....www....www..",
+            "..www....www....www..",
+            "..aaa....lll....fff..",
+            "..aaa....lll....fff..",
+            "#####################",
+        ],
+        40,
+    );
+}
+
+#[test]
+fn drain() {
+    check(
+        "drain",
+        &[
+            "..wwwwwwwwww..",
+            "..oooooooooo..",
+            "..............",
+            "..............",
+            "dddddddddddddd",
+        ],
+        40,
+    );
+}
+
+#[test]
+fn fire() {
+    // Fire burning wood into ash and smoke, and igniting oil
+    check(
+        "fire",
+        &[
+            "...............",
+            "...............",
+            "...WWWW..oooo..",
+            "...WWWW..oooo..",
+            "...ffff..ffff..",
+            "###############",
+        ],
+        60,
+    );
+}
+
+#[test]
+fn ash() {
+    check(
+        "ash",
+        &[
+            "..hhhhhhhh..",
+            "..hhhhhhhh..",
+            "............",
+            "............",
+        ],
+        1500,
+    );
+}
+
+#[test]
+fn lava() {
+    // Lava giving off sparks and cooling down into rock
+    check(
+        "lava",
+        &[
+            "..............",
+            "..............",
+            "..www.........",
+            "..lllllll.....",
+            "..lllllll.....",
+        ],
+        150,
+    );
+}
+
+#[test]
+fn smoke() {
+    check(
+        "smoke",
+        &[
+            "............",
+            "............",
+            "............",
+            "....~~~~....",
+            "....~~~~....",
+        ],
+        40,
+    );
+}
+
+#[test]
+fn iron() {
+    check(
+        "iron",
+        &["...www......", "IIIIIIIIIIII", "IIIIIIIIIIII"],
+        600,
+    );
+}
+
+#[test]
+fn plant() {
+    // Seeds on wet sand growing into plants
+    check(
+        "plant",
+        &[
+            "..............",
+            "..............",
+            "..............",
+            "..............",
+            "...,....,.....",
+            "ssssssssssssss",
+            "wwwwwwwwwwwwww",
+        ],
+        300,
+    );
+}
+
+#[test]
+fn tnt() {
+    // A fuse lit by a fire source setting off TNT
+    check(
+        "tnt",
+        &[
+            "..............",
+            "....TTTT......",
+            "....TTTT......",
+            "4-------......",
+            "##############",
+        ],
+        60,
+    );
+}
+
+#[test]
+fn explosion() {
+    check(
+        "explosion",
+        &[
+            "...............",
+            "...sss...###...",
+            "...sss...###...",
+            ".......*.......",
+            "...............",
+        ],
+        20,
+    );
+}
+
+#[test]
+fn life() {
+    // Air cells come alive next to exactly three living cells
+    check(
+        "life",
+        &[
+            ".L..........",
+            "..L.........",
+            "LLL.........",
+            "............",
+            "............",
+            "............",
+            "............",
+        ],
+        8,
+    );
+}
+
+#[test]
+fn acid() {
+    check(
+        "acid",
+        &[
+            "..aaaa..aaaa..",
+            "..aaaa..aaaa..",
+            "..####..IIII..",
+            "..####..IIII..",
+        ],
+        60,
+    );
+}
+
+#[test]
+fn sources() {
+    check(
+        "sources",
+        &[
+            "..1....2....3..",
+            "...............",
+            "...............",
+            ".....4.....5...",
+            "...............",
+        ],
+        20,
+    );
+}
+
+// Custom element falling straight down, leaving sand behind
+struct Dropper;
+
+impl CustomUpdate for Dropper {
+    fn defines(&self, element: Element) -> bool {
+        element == Element::Custom1
+    }
+
+    fn update(&self, _element: Element, neighbourhood: &mut Neighbourhood) {
+        if neighbourhood.get(0, 1).map(|cell| cell.element) == Some(Element::Air) {
+            neighbourhood.swap(0, 1);
+            neighbourhood.set_element(0, 0, Element::Sand);
+        }
+    }
+}
+
+#[test]
+fn custom() {
+    let mut simulation = Simulation::new();
+    simulation.custom_update = Some(Arc::new(Dropper));
+    check_scenario(
+        "custom",
+        &[
+            "..A....A..B.",
+            "............",
+            "............",
+            "............",
+            "............",
+        ],
+        10,
+        simulation,
+    );
+}
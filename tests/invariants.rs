@@ -0,0 +1,139 @@
+mod common;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use common::Wanderer;
+use falling_rust::element::{Element, ELEMENT_COUNT};
+use falling_rust::sandbox::SandBox;
+use falling_rust::simulation::{simulation_step, Simulation};
+use falling_rust::toolbox::{Tool, ToolBox, EDITOR_ELEMENTS};
+use proptest::prelude::*;
+
+// Elements that only move around and never react with each other
+const INERT_ELEMENTS: [Element; 5] = [
+    Element::Air,
+    Element::Sand,
+    Element::Rock,
+    Element::Water,
+    Element::Oil,
+];
+
+fn border_intact(sandbox: &SandBox) -> bool {
+    let (width, height) = (sandbox.width(), sandbox.height());
+    (0..width).all(|x| {
+        sandbox.get(x, 0).element == Element::Indestructible
+            && sandbox.get(x, height - 1).element == Element::Indestructible
+    }) && (0..height).all(|y| {
+        sandbox.get(0, y).element == Element::Indestructible
+            && sandbox.get(width - 1, y).element == Element::Indestructible
+    })
+}
+
+fn counted_elements(sandbox: &SandBox) -> [usize; ELEMENT_COUNT] {
+    let mut counts = [0; ELEMENT_COUNT];
+    for y in 0..sandbox.height() {
+        for x in 0..sandbox.width() {
+            counts[sandbox.get(x, y).element as usize] += 1;
+        }
+    }
+    counts
+}
+
+fn all_visited(sandbox: &SandBox) -> bool {
+    (1..sandbox.height() - 1).all(|y| {
+        (1..sandbox.width() - 1).all(|x| sandbox.get(x, y).visited == sandbox.is_visited_state())
+    })
+}
+
+fn sandbox_from(width: usize, height: usize, elements: &[Element]) -> SandBox {
+    let mut sandbox = SandBox::new(width, height);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let element = elements[(x + y * width) % elements.len()];
+            sandbox.set_element(x, y, element, (x * 7 + y * 13) as u32);
+        }
+    }
+    sandbox
+}
+
+// A tool used somewhere in the sandbox, including right at the edges
+fn tool_application() -> impl Strategy<Value = (Tool, Element, usize, usize, usize)> {
+    (
+        prop::sample::select(Tool::ALL.to_vec()),
+        prop::sample::select(EDITOR_ELEMENTS.to_vec()),
+        1usize..40,
+        any::<prop::sample::Index>(),
+        any::<prop::sample::Index>(),
+    )
+        .prop_map(|(tool, element, size, x, y)| (tool, element, size, x.index(1000), y.index(1000)))
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn border_survives_tools_and_steps(
+        width in 3usize..48,
+        height in 3usize..48,
+        elements in prop::collection::vec(prop::sample::select(EDITOR_ELEMENTS.to_vec()), 1..12),
+        applications in prop::collection::vec(tool_application(), 0..8),
+    ) {
+        let mut sandbox = sandbox_from(width, height, &elements);
+        let mut simulation = Simulation::new();
+        let mut toolbox = ToolBox::default();
+        for (step, (tool, element, size, x, y)) in applications.into_iter().enumerate() {
+            toolbox.tool = tool;
+            toolbox.element = element;
+            toolbox.tool_size = size;
+            toolbox.apply(&mut sandbox, x % width, y % height);
+            for _ in 0..step + 1 {
+                simulation_step(&mut simulation, &mut sandbox);
+                prop_assert!(border_intact(&sandbox));
+            }
+        }
+        prop_assert_eq!(sandbox.element_counts(), &counted_elements(&sandbox));
+    }
+
+    #[test]
+    fn inert_elements_are_conserved(
+        width in 3usize..40,
+        height in 3usize..40,
+        elements in prop::collection::vec(prop::sample::select(INERT_ELEMENTS.to_vec()), 1..8),
+        steps in 1usize..60,
+    ) {
+        let mut sandbox = sandbox_from(width, height, &elements);
+        let mut simulation = Simulation::new();
+        let counts = counted_elements(&sandbox);
+        for _ in 0..steps {
+            simulation_step(&mut simulation, &mut sandbox);
+            prop_assert_eq!(counted_elements(&sandbox), counts);
+        }
+        prop_assert_eq!(sandbox.element_counts(), &counts);
+    }
+
+    #[test]
+    fn cells_are_updated_once_per_step(
+        width in 3usize..40,
+        height in 3usize..40,
+        elements in prop::collection::vec(
+            prop::sample::select(vec![Element::Air, Element::Custom1, Element::Rock]),
+            1..8,
+        ),
+        steps in 1usize..30,
+    ) {
+        let updates = Arc::new(AtomicUsize::new(0));
+        let mut simulation = Simulation::new();
+        simulation.custom_update = Some(Arc::new(Wanderer { updates: updates.clone() }));
+        let mut sandbox = sandbox_from(width, height, &elements);
+        for _ in 0..steps {
+            updates.store(0, Ordering::Relaxed);
+            simulation_step(&mut simulation, &mut sandbox);
+            // Cells that moved ahead of the update order are not updated again
+            prop_assert_eq!(updates.load(Ordering::Relaxed), sandbox.count(Element::Custom1));
+            prop_assert!(all_visited(&sandbox));
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    io::{ErrorKind, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use falling_rust::element::Element;
+use falling_rust::lockstep::{Lockstep, LockstepMessage, LockstepPeer, HASH_INTERVAL};
+use falling_rust::network::ToolApplication;
+use falling_rust::sandbox::SandBox;
+use falling_rust::simulation::Simulation;
+use falling_rust::toolbox::Tool;
+
+struct Peer {
+    lockstep: Lockstep,
+    simulation: Simulation,
+    sandbox: SandBox,
+}
+
+impl Peer {
+    fn new(player: usize) -> Self {
+        Peer {
+            lockstep: Lockstep::new(player, 2),
+            simulation: Simulation::new(),
+            sandbox: SandBox::new(64, 64),
+        }
+    }
+}
+
+fn sand(x: usize, y: usize) -> ToolApplication {
+    ToolApplication {
+        tool: Tool::Circle,
+        element: Element::Sand,
+        tool_size: 6,
+        x,
+        y,
+    }
+}
+
+// Step both peers, delivering the messages of each to the other
+fn run(peers: &mut [Peer; 2], frames: u64) {
+    for _ in 0..frames {
+        let messages: Vec<_> = peers
+            .iter_mut()
+            .map(|peer| peer.lockstep.step(&mut peer.simulation, &mut peer.sandbox))
+            .collect();
+        for (index, messages) in messages.into_iter().enumerate() {
+            for message in messages {
+                peers[1 - index].lockstep.receive(message);
+            }
+        }
+    }
+}
+
+#[test]
+fn peers_stay_in_sync() {
+    let mut peers = [Peer::new(0), Peer::new(1)];
+    for frame in 0..HASH_INTERVAL * 3 {
+        if frame % 5 == 0 {
+            peers[0].lockstep.queue(sand(20, 10));
+            peers[1].lockstep.queue(ToolApplication {
+                element: Element::Water,
+                ..sand(40, 10)
+            });
+        }
+        run(&mut peers, 1);
+    }
+    assert_eq!(peers[0].lockstep.frame(), HASH_INTERVAL * 3);
+    assert!(peers[0].sandbox.count(Element::Sand) > 0);
+    assert!(peers[0].sandbox.count(Element::Water) > 0);
+    assert_eq!(
+        peers[0].simulation.state_hash(&peers[0].sandbox),
+        peers[1].simulation.state_hash(&peers[1].sandbox)
+    );
+    assert_eq!(peers[0].lockstep.desync(), None);
+    assert_eq!(peers[1].lockstep.desync(), None);
+}
+
+#[test]
+fn waits_for_the_inputs_of_other_players() {
+    let mut peer = Peer::new(0);
+    for _ in 0..HASH_INTERVAL {
+        peer.lockstep.step(&mut peer.simulation, &mut peer.sandbox);
+    }
+    // Only the frames without inputs can be simulated without hearing from the other player
+    assert!(peer.lockstep.frame() < HASH_INTERVAL);
+    assert!(!peer.lockstep.ready());
+}
+
+#[test]
+fn detects_desync() {
+    let mut peers = [Peer::new(0), Peer::new(1)];
+    run(&mut peers, 5);
+    // A change not made through the lockstep inputs
+    peers[1].sandbox.set_element(30, 30, Element::Rock, 0);
+    run(&mut peers, HASH_INTERVAL);
+    assert_eq!(peers[0].lockstep.desync(), Some(HASH_INTERVAL));
+    assert_eq!(peers[1].lockstep.desync(), Some(HASH_INTERVAL));
+}
+
+#[test]
+fn ignores_messages_for_frames_far_ahead() {
+    let mut peers = [Peer::new(0), Peer::new(1)];
+    // No peer can be this far ahead, so the hash is dropped instead of kept until the frame
+    peers[0].lockstep.receive(LockstepMessage::Hash {
+        player: 1,
+        frame: HASH_INTERVAL,
+        hash: 0,
+    });
+    run(&mut peers, HASH_INTERVAL);
+    assert_eq!(peers[0].lockstep.frame(), HASH_INTERVAL);
+    assert_eq!(peers[0].lockstep.desync(), None);
+}
+
+#[test]
+fn peers_play_over_loopback() {
+    let mut host = LockstepPeer::host("127.0.0.1:0", 2).unwrap();
+    let mut guest = LockstepPeer::join(host.local_addr().unwrap()).unwrap();
+    let size = (32, 32);
+    let mut started = (None, None);
+    for _ in 0..100 {
+        started.0 = started.0.or(host.poll(size).unwrap());
+        started.1 = started.1.or(guest.poll((8, 8)).unwrap());
+        if started.0.is_some() && started.1.is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    // The guest starts with the sandbox size of the host
+    assert_eq!(started, (Some(size), Some(size)));
+    assert_eq!(guest.lockstep.as_ref().unwrap().player(), 1);
+
+    let mut states = [
+        (Simulation::new(), SandBox::new(32, 32)),
+        (Simulation::new(), SandBox::new(32, 32)),
+    ];
+    guest.lockstep.as_mut().unwrap().queue(sand(16, 4));
+    for _ in 0..1000 {
+        for (peer, (simulation, sandbox)) in [&mut host, &mut guest].into_iter().zip(&mut states) {
+            peer.poll(size).unwrap();
+            let messages = peer.lockstep.as_mut().unwrap().step(simulation, sandbox);
+            peer.send(&messages).unwrap();
+        }
+        if host.lockstep.as_ref().unwrap().frame() >= HASH_INTERVAL * 2
+            && guest.lockstep.as_ref().unwrap().frame() >= HASH_INTERVAL * 2
+        {
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(host.lockstep.as_ref().unwrap().frame() >= HASH_INTERVAL * 2);
+    assert!(states[0].1.count(Element::Sand) > 0);
+    assert_eq!(host.lockstep.as_ref().unwrap().desync(), None);
+    assert_eq!(guest.lockstep.as_ref().unwrap().desync(), None);
+}
+
+#[test]
+fn guests_refuse_invalid_sessions() {
+    // Player, players, width and height as sent by a host
+    let sessions = [
+        (0, 0, 32, 32),
+        (2, 2, 32, 32),
+        (1, 2, 0, 32),
+        (1, 2, 32, 100_000),
+    ];
+    for (player, players, width, height) in sessions {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut guest = LockstepPeer::join(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut welcome = vec![11, 0, 0, 0, 10, player, players];
+        welcome.extend_from_slice(&(width as u32).to_le_bytes());
+        welcome.extend_from_slice(&(height as u32).to_le_bytes());
+        stream.write_all(&welcome).unwrap();
+
+        let mut result = Ok(None);
+        for _ in 0..100 {
+            result = guest.poll((8, 8));
+            if !matches!(result, Ok(None)) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(guest.lockstep.is_none());
+    }
+}
+
+#[test]
+fn host_refuses_guests_speaking_for_others() {
+    let mut host = LockstepPeer::host("127.0.0.1:0", 2).unwrap();
+    let mut guest = TcpStream::connect(host.local_addr().unwrap()).unwrap();
+    // Inputs for frame 4 of player 0, which is the host and not this guest
+    let mut inputs = vec![10, 0, 0, 0, 11, 0];
+    inputs.extend_from_slice(&4u64.to_le_bytes());
+    guest.write_all(&inputs).unwrap();
+
+    let mut result = Ok(None);
+    for _ in 0..100 {
+        result = host.poll((32, 32));
+        if result.is_err() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+}
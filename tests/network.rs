@@ -0,0 +1,194 @@
+use std::{thread, time::Duration};
+
+use falling_rust::element::Element;
+use falling_rust::network::{Client, Server, ToolApplication};
+use falling_rust::sandbox::SandBox;
+use falling_rust::simulation::{simulation_step, Simulation};
+use falling_rust::toolbox::Tool;
+
+fn assert_same_cells(a: &SandBox, b: &SandBox) {
+    assert_eq!((a.width(), a.height()), (b.width(), b.height()));
+    for y in 0..a.height() {
+        for x in 0..a.width() {
+            let (cell_a, cell_b) = (a.get(x, y), b.get(x, y));
+            assert_eq!(
+                (cell_a.element, cell_a.variant, cell_a.strength),
+                (cell_b.element, cell_b.variant, cell_b.strength),
+                "cell {}, {}",
+                x,
+                y
+            );
+        }
+    }
+}
+
+// Run the server until the client received everything it sent
+fn sync(
+    server: &mut Server,
+    server_sandbox: &mut SandBox,
+    client: &mut Client,
+    replica: &mut SandBox,
+) {
+    for _ in 0..100 {
+        server.receive(server_sandbox);
+        server.send_changes(server_sandbox);
+        thread::sleep(Duration::from_millis(5));
+        client.receive(replica).unwrap();
+    }
+}
+
+#[test]
+fn client_follows_the_server_on_loopback() {
+    let mut server = Server::bind("127.0.0.1:0").unwrap();
+    let mut server_sandbox = SandBox::new(64, 48);
+    let mut simulation = Simulation::new();
+    let mut client = Client::connect(server.local_addr().unwrap()).unwrap();
+    let mut replica = SandBox::new(8, 8);
+
+    // A new client receives the complete sandbox
+    sync(&mut server, &mut server_sandbox, &mut client, &mut replica);
+    assert_eq!(server.client_count(), 1);
+    assert_same_cells(&server_sandbox, &replica);
+
+    // Tools used by the client are applied by the server
+    client
+        .send(ToolApplication {
+            tool: Tool::Square,
+            element: Element::Sand,
+            tool_size: 8,
+            x: 32,
+            y: 10,
+        })
+        .unwrap();
+    sync(&mut server, &mut server_sandbox, &mut client, &mut replica);
+    assert_eq!(server_sandbox.count(Element::Sand), 64);
+    assert_same_cells(&server_sandbox, &replica);
+
+    // Simulation steps arrive as changed cells
+    for _ in 0..30 {
+        server.receive(&mut server_sandbox);
+        simulation_step(&mut simulation, &mut server_sandbox);
+        server.send_changes(&server_sandbox);
+        thread::sleep(Duration::from_millis(2));
+        client.receive(&mut replica).unwrap();
+    }
+    sync(&mut server, &mut server_sandbox, &mut client, &mut replica);
+    assert_eq!(replica.count(Element::Sand), 64);
+    assert_same_cells(&server_sandbox, &replica);
+}
+
+#[test]
+fn server_drops_disconnected_clients() {
+    let mut server = Server::bind("127.0.0.1:0").unwrap();
+    let mut sandbox = SandBox::new(16, 16);
+    let client = Client::connect(server.local_addr().unwrap()).unwrap();
+    for _ in 0..100 {
+        server.send_changes(&sandbox);
+        if server.client_count() == 1 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(server.client_count(), 1);
+
+    drop(client);
+    for _ in 0..100 {
+        server.receive(&mut sandbox);
+        if server.client_count() == 0 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(server.client_count(), 0);
+}
+
+#[test]
+fn server_drops_clients_drawing_walls() {
+    let mut server = Server::bind("127.0.0.1:0").unwrap();
+    let mut server_sandbox = SandBox::new(64, 48);
+    let mut client = Client::connect(server.local_addr().unwrap()).unwrap();
+    let mut replica = SandBox::new(8, 8);
+    sync(&mut server, &mut server_sandbox, &mut client, &mut replica);
+    assert_eq!(server.client_count(), 1);
+    let walls = server_sandbox.count(Element::Indestructible);
+
+    // Indestructible cells cannot be erased again, so only the editor elements are accepted
+    client
+        .send(ToolApplication {
+            tool: Tool::Square,
+            element: Element::Indestructible,
+            tool_size: 8,
+            x: 32,
+            y: 10,
+        })
+        .unwrap();
+    for _ in 0..100 {
+        server.receive(&mut server_sandbox);
+        if server.client_count() == 0 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(server.client_count(), 0);
+    assert_eq!(server_sandbox.count(Element::Indestructible), walls);
+}
+
+#[test]
+fn changes_for_a_replaced_sandbox_are_refused() {
+    let mut server = Server::bind("127.0.0.1:0").unwrap();
+    let mut server_sandbox = SandBox::new(64, 48);
+    let mut client = Client::connect(server.local_addr().unwrap()).unwrap();
+    let mut replica = SandBox::new(8, 8);
+    sync(&mut server, &mut server_sandbox, &mut client, &mut replica);
+
+    // Changes to cells the client no longer has are an error instead of a panic
+    let mut replica = SandBox::new(16, 16);
+    server_sandbox.set_element(32, 10, Element::Sand, 0);
+    server.send_changes(&server_sandbox);
+    let mut result = Ok(false);
+    for _ in 0..100 {
+        result = client.receive(&mut replica);
+        if result.is_err() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert!(result.is_err());
+}
+
+#[test]
+fn snapshots_with_invalid_sizes_are_refused() {
+    let bytes = SandBox::new(16, 8).to_bytes();
+    assert!(SandBox::from_bytes(&bytes).is_some());
+
+    // Sizes beyond the largest sandbox, or not matching the cells, are refused before allocating
+    for (width, height) in [(u32::MAX, u32::MAX), (2048, 8), (16, 9), (16, 7)] {
+        let mut corrupt = bytes.clone();
+        corrupt[0..4].copy_from_slice(&width.to_le_bytes());
+        corrupt[4..8].copy_from_slice(&height.to_le_bytes());
+        assert!(SandBox::from_bytes(&corrupt).is_none());
+    }
+}
+
+#[test]
+fn snapshots_without_a_border_are_refused() {
+    let mut sandbox = SandBox::new(16, 8);
+    sandbox.set_element(5, 5, Element::Sand, 0);
+    assert!(SandBox::from_bytes_with_border(&sandbox.to_bytes()).is_some());
+
+    // The simulation relies on the indestructible border to stay within the sandbox
+    for (x, y) in [(0, 0), (5, 0), (15, 3), (0, 7), (9, 7)] {
+        let mut bytes = SandBox::new(16, 8).to_bytes();
+        let mut index = 0;
+        for run in bytes[8..].chunks_mut(4) {
+            let cells = run[0] as usize;
+            if (index..index + cells).contains(&(x + y * 16)) {
+                // Turn the run of wall holding the cell into sand
+                run[1] = Element::Sand as u8;
+            }
+            index += cells;
+        }
+        assert!(SandBox::from_bytes(&bytes).is_some());
+        assert!(SandBox::from_bytes_with_border(&bytes).is_none());
+    }
+}
@@ -0,0 +1,105 @@
+use falling_rust::cell::Cell;
+use falling_rust::element::{element_type, Element, RenderMethod, ELEMENT_COUNT};
+use falling_rust::pseudo_random::PseudoRandom;
+use falling_rust::render::{cell_color, copy_dirty_cells, render_colors};
+use falling_rust::sandbox::SandBox;
+use falling_rust::simulation::{simulation_step, Simulation};
+use falling_rust::theme::{gradient_color, Theme};
+
+fn all_elements() -> impl Iterator<Item = Element> {
+    (0..ELEMENT_COUNT as u8).filter_map(|value| Element::try_from(value).ok())
+}
+
+#[test]
+fn colors_are_looked_up_from_the_gradients() {
+    let mut random = PseudoRandom::new();
+    for theme in Theme::ALL {
+        for element in all_elements() {
+            let element_type = element_type(element);
+            let gradient = theme.gradient(element);
+            for value in 0..=u8::MAX {
+                let cell = Cell {
+                    element,
+                    variant: value,
+                    strength: value,
+                    visited: false,
+                };
+                let expected = match element_type.render {
+                    RenderMethod::FixedColor => gradient[gradient.len() - 1],
+                    RenderMethod::StrengthLinear => {
+                        gradient_color(gradient, value, element_type.strength)
+                    }
+                    RenderMethod::VariantLinear => gradient_color(gradient, value, u8::MAX),
+                    RenderMethod::Flicker | RenderMethod::Texture(_) => continue,
+                };
+                assert_eq!(cell_color(&cell, 0, 0, theme, &mut random), expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn runs_of_cells_render_like_single_cells() {
+    let size = 64;
+    let mut sandbox = SandBox::new(size, size);
+    let layers = [
+        Element::Sand,
+        Element::Water,
+        Element::Wood,
+        Element::Fire,
+        Element::Rock,
+    ];
+    for (layer, element) in layers.into_iter().enumerate() {
+        for y in 10 + layer * 8..14 + layer * 8 {
+            for x in 1..size - 1 {
+                sandbox.set_element(x, y, element, (x * y) as u32);
+            }
+        }
+    }
+    let mut simulation = Simulation::new();
+    for _ in 0..10 {
+        simulation_step(&mut simulation, &mut sandbox);
+    }
+    for theme in Theme::ALL {
+        let mut random = PseudoRandom::new();
+        let mut expected = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let (r, g, b) = cell_color(sandbox.get(x, y), x, y, theme, &mut random);
+                expected.extend_from_slice(&[r, g, b, 255]);
+            }
+        }
+        assert_eq!(render_colors(&mut sandbox, theme), expected);
+    }
+}
+
+#[test]
+fn only_changed_chunks_are_copied_for_the_shader() {
+    let mut sandbox = SandBox::new(40, 40);
+    sandbox.set_element(20, 20, Element::Sand, 0);
+    let cell_bytes = |sandbox: &SandBox, x: usize, y: usize| {
+        let cell = sandbox.get(x, y);
+        [cell.element as u8, cell.variant, cell.strength, 255]
+    };
+
+    // A new sandbox is copied completely
+    let mut data = vec![0; 40 * 40 * 4];
+    copy_dirty_cells(&sandbox, &mut data);
+    for (index, pixel) in data.chunks(4).enumerate() {
+        assert_eq!(pixel, cell_bytes(&sandbox, index % 40, index / 40));
+    }
+
+    // After that only the chunk with the changed cell
+    sandbox.clear_dirty_chunks();
+    sandbox.set_element(3, 35, Element::Water, 0);
+    data.fill(0);
+    copy_dirty_cells(&sandbox, &mut data);
+    for (index, pixel) in data.chunks(4).enumerate() {
+        let (x, y) = (index % 40, index / 40);
+        if x < 16 && y >= 32 {
+            assert_eq!(pixel, cell_bytes(&sandbox, x, y));
+        } else {
+            assert_eq!(pixel, [0; 4]);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use falling_rust::element::Element;
+use falling_rust::sandbox::SandBox;
+use falling_rust::simulation::{simulation_step, Simulation};
+
+fn scenario() -> (Simulation, SandBox) {
+    let mut sandbox = SandBox::new(48, 48);
+    for x in 10..30 {
+        sandbox.set_element(x, 5, Element::Sand, 0);
+        sandbox.set_element(x, 8, Element::Water, 0);
+    }
+    sandbox.set_element(24, 30, Element::Fire, 0);
+    sandbox.set_element(25, 30, Element::Wood, 0);
+    (Simulation::new(), sandbox)
+}
+
+#[test]
+fn same_runs_have_the_same_hash() {
+    let (mut simulation_a, mut sandbox_a) = scenario();
+    let (mut simulation_b, mut sandbox_b) = scenario();
+    assert_eq!(sandbox_a.state_hash(), sandbox_b.state_hash());
+    for _ in 0..100 {
+        simulation_step(&mut simulation_a, &mut sandbox_a);
+        simulation_step(&mut simulation_b, &mut sandbox_b);
+    }
+    // Bookkeeping that does not change the outcome is not part of the hash
+    sandbox_b.clear_dirty_chunks();
+    sandbox_b.render_time_ms = 10;
+    assert_eq!(sandbox_a.state_hash(), sandbox_b.state_hash());
+    assert_eq!(
+        simulation_a.state_hash(&sandbox_a),
+        simulation_b.state_hash(&sandbox_b)
+    );
+}
+
+#[test]
+fn hash_changes_with_the_state() {
+    let (mut simulation, mut sandbox) = scenario();
+    let hash = sandbox.state_hash();
+
+    sandbox.get_mut(20, 20).variant = 1;
+    assert_ne!(sandbox.state_hash(), hash);
+    sandbox.get_mut(20, 20).variant = 0;
+    assert_eq!(sandbox.state_hash(), hash);
+
+    sandbox.toggle_visited_state();
+    assert_ne!(sandbox.state_hash(), hash);
+    sandbox.toggle_visited_state();
+
+    // The random number generator decides the next steps as well
+    let full_hash = simulation.state_hash(&sandbox);
+    simulation.random.next();
+    assert_eq!(sandbox.state_hash(), hash);
+    assert_ne!(simulation.state_hash(&sandbox), full_hash);
+}
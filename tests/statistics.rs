@@ -0,0 +1,50 @@
+use falling_rust::element::{Element, ELEMENT_COUNT};
+use falling_rust::sandbox::SandBox;
+use falling_rust::simulation::{simulation_step, Simulation};
+
+fn recount(sandbox: &SandBox) -> [usize; ELEMENT_COUNT] {
+    let mut counts = [0; ELEMENT_COUNT];
+    for y in 0..sandbox.height() {
+        for x in 0..sandbox.width() {
+            counts[sandbox.get(x, y).element as usize] += 1;
+        }
+    }
+    counts
+}
+
+#[test]
+fn element_counts_match_cells_while_simulating() {
+    let size = 64;
+    let mut simulation = Simulation::new();
+    let mut sandbox = SandBox::new(size, size);
+    for x in 1..size - 1 {
+        sandbox.set_element(x, size - 2, Element::Wood, 0);
+        sandbox.set_element(x, size - 3, Element::TNT, 0);
+        sandbox.set_element(x, 10, Element::Water, 0);
+        sandbox.set_element(x, 20, Element::Acid, 0);
+        sandbox.set_element(x, 30, Element::Lava, 0);
+    }
+    sandbox.set_element(size / 2, size - 4, Element::Fire, 0);
+    for _ in 0..200 {
+        simulation_step(&mut simulation, &mut sandbox);
+        assert_eq!(*sandbox.element_counts(), recount(&sandbox));
+    }
+}
+
+#[test]
+fn all_tnt_explodes() {
+    let size = 64;
+    let mut simulation = Simulation::new();
+    let mut sandbox = SandBox::new(size, size);
+    for x in 10..20 {
+        for y in 40..50 {
+            sandbox.set_element(x, y, Element::TNT, 0);
+        }
+    }
+    sandbox.set_element(15, 39, Element::Fire, 0);
+    assert_eq!(sandbox.count(Element::TNT), 100);
+    for _ in 0..200 {
+        simulation_step(&mut simulation, &mut sandbox);
+    }
+    assert_eq!(sandbox.count(Element::TNT), 0);
+}
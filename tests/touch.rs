@@ -0,0 +1,108 @@
+use bevy::input::touch::{TouchInput, TouchPhase};
+use bevy::math::Vec2;
+use falling_rust::touch::{Gesture, TouchGestures, LONG_PRESS_SECONDS};
+
+const FRAME: f32 = 1.0 / 60.0;
+
+fn touch(gestures: &mut TouchGestures, phase: TouchPhase, id: u64, x: f32, y: f32) {
+    gestures.handle(&TouchInput {
+        phase,
+        position: Vec2::new(x, y),
+        force: None,
+        id,
+    });
+}
+
+#[test]
+fn one_finger_draws_while_moving() {
+    let mut gestures = TouchGestures::default();
+    touch(&mut gestures, TouchPhase::Started, 0, 10.0, 10.0);
+    assert_eq!(gestures.update(FRAME), Gesture::None);
+    touch(&mut gestures, TouchPhase::Moved, 0, 30.0, 10.0);
+    assert_eq!(gestures.update(FRAME), Gesture::Draw(Vec2::new(30.0, 10.0)));
+    touch(&mut gestures, TouchPhase::Moved, 0, 40.0, 20.0);
+    assert_eq!(gestures.update(FRAME), Gesture::Draw(Vec2::new(40.0, 20.0)));
+    touch(&mut gestures, TouchPhase::Ended, 0, 40.0, 20.0);
+    assert_eq!(gestures.update(FRAME), Gesture::None);
+    assert!(!gestures.is_active());
+}
+
+#[test]
+fn tap_draws_once() {
+    let mut gestures = TouchGestures::default();
+    touch(&mut gestures, TouchPhase::Started, 0, 10.0, 10.0);
+    touch(&mut gestures, TouchPhase::Ended, 0, 11.0, 10.0);
+    assert_eq!(gestures.update(FRAME), Gesture::Draw(Vec2::new(11.0, 10.0)));
+    assert_eq!(gestures.update(FRAME), Gesture::None);
+}
+
+#[test]
+fn long_press_erases() {
+    let mut gestures = TouchGestures::default();
+    touch(&mut gestures, TouchPhase::Started, 0, 10.0, 10.0);
+    assert_eq!(gestures.update(LONG_PRESS_SECONDS / 2.0), Gesture::None);
+    touch(&mut gestures, TouchPhase::Moved, 0, 12.0, 10.0);
+    assert_eq!(
+        gestures.update(LONG_PRESS_SECONDS / 2.0),
+        Gesture::Erase(Vec2::new(12.0, 10.0))
+    );
+    touch(&mut gestures, TouchPhase::Moved, 0, 50.0, 10.0);
+    assert_eq!(
+        gestures.update(FRAME),
+        Gesture::Erase(Vec2::new(50.0, 10.0))
+    );
+}
+
+#[test]
+fn two_fingers_pan_and_pinch() {
+    let mut gestures = TouchGestures::default();
+    touch(&mut gestures, TouchPhase::Started, 0, 100.0, 100.0);
+    touch(&mut gestures, TouchPhase::Started, 1, 200.0, 100.0);
+    assert_eq!(
+        gestures.update(FRAME),
+        Gesture::PanZoom {
+            center: Vec2::new(150.0, 100.0),
+            movement: Vec2::ZERO,
+            zoom: 1.0,
+        }
+    );
+
+    // Both fingers move the same way
+    touch(&mut gestures, TouchPhase::Moved, 0, 110.0, 120.0);
+    touch(&mut gestures, TouchPhase::Moved, 1, 210.0, 120.0);
+    assert_eq!(
+        gestures.update(FRAME),
+        Gesture::PanZoom {
+            center: Vec2::new(160.0, 120.0),
+            movement: Vec2::new(-10.0, -20.0),
+            zoom: 1.0,
+        }
+    );
+
+    // Spreading the fingers zooms in
+    touch(&mut gestures, TouchPhase::Moved, 0, 60.0, 120.0);
+    touch(&mut gestures, TouchPhase::Moved, 1, 260.0, 120.0);
+    let Gesture::PanZoom { movement, zoom, .. } = gestures.update(FRAME) else {
+        panic!("expected a pan and zoom gesture");
+    };
+    assert_eq!(movement, Vec2::ZERO);
+    assert!((zoom - 0.5).abs() < 1e-6);
+
+    // The remaining finger does not draw after lifting one
+    touch(&mut gestures, TouchPhase::Ended, 1, 260.0, 120.0);
+    touch(&mut gestures, TouchPhase::Moved, 0, 10.0, 10.0);
+    assert_eq!(gestures.update(FRAME), Gesture::None);
+    touch(&mut gestures, TouchPhase::Ended, 0, 10.0, 10.0);
+    assert_eq!(gestures.update(FRAME), Gesture::None);
+    assert!(!gestures.is_active());
+}
+
+#[test]
+fn second_finger_cancels_drawing() {
+    let mut gestures = TouchGestures::default();
+    touch(&mut gestures, TouchPhase::Started, 0, 10.0, 10.0);
+    touch(&mut gestures, TouchPhase::Moved, 0, 40.0, 10.0);
+    assert!(matches!(gestures.update(FRAME), Gesture::Draw(_)));
+    touch(&mut gestures, TouchPhase::Started, 1, 80.0, 10.0);
+    assert!(matches!(gestures.update(FRAME), Gesture::PanZoom { .. }));
+}
@@ -0,0 +1,14 @@
+use falling_rust::language::Language;
+
+#[test]
+fn all_languages_define_all_keys() {
+    for language in Language::all() {
+        let missing = language.missing_keys();
+        assert!(
+            missing.is_empty(),
+            "language '{}' is missing keys: {:?}",
+            language,
+            missing
+        );
+    }
+}
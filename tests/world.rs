@@ -0,0 +1,194 @@
+mod common;
+
+use std::{
+    env, fs,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use common::Wanderer;
+use falling_rust::conservation::MassBalance;
+use falling_rust::element::{Element, ELEMENT_COUNT};
+use falling_rust::simulation::Simulation;
+use falling_rust::toolbox::{Tool, ToolBox};
+use falling_rust::world::{ChunkWorld, WORLD_CHUNK_SIZE};
+
+fn count(world: &ChunkWorld, element: Element) -> usize {
+    world
+        .chunk_positions()
+        .filter_map(|position| world.chunk(position))
+        .map(|chunk| chunk.count(element))
+        .sum()
+}
+
+// Sand above a floor of rock, across the chunks around the origin
+fn sand_on_rock(world: &mut ChunkWorld, x: i64, y: i64) {
+    for dx in -20..20 {
+        world.set_element(x + dx, y + 40, Element::Rock, 0);
+        for dy in 0..10 {
+            world.set_element(x + dx / 2, y + dy, Element::Sand, 0);
+        }
+    }
+}
+
+#[test]
+fn chunks_are_allocated_when_needed() {
+    let mut world = ChunkWorld::new(None);
+    assert_eq!(world.chunk_positions().count(), 0);
+    world.set_element(-1, -1, Element::Air, 0);
+    assert_eq!(world.chunk_positions().count(), 0);
+
+    world.set_element(-1, -1, Element::Sand, 0);
+    assert_eq!(world.chunk_positions().collect::<Vec<_>>(), vec![(-1, -1)]);
+    assert_eq!(world.get(-1, -1).element, Element::Sand);
+
+    // Falling sand moves into the chunk below
+    let mut simulation = Simulation::new();
+    for _ in 0..10 {
+        world.step(&mut simulation);
+    }
+    assert_eq!(world.get(-1, 9).element, Element::Sand);
+    assert!(world.chunk((-1, 0)).is_some());
+    assert_eq!(count(&world, Element::Sand), 1);
+}
+
+#[test]
+fn sand_piles_up_across_chunks() {
+    let mut world = ChunkWorld::new(None);
+    sand_on_rock(&mut world, 0, -20);
+    let sand = count(&world, Element::Sand);
+    let mut simulation = Simulation::new();
+    for _ in 0..200 {
+        world.step(&mut simulation);
+        assert_eq!(count(&world, Element::Sand), sand);
+    }
+    // All sand rests on the floor, on both sides of the chunk border
+    assert_eq!(world.get(-1, 19).element, Element::Sand);
+    assert_eq!(world.get(0, 19).element, Element::Sand);
+    for x in -20..20 {
+        for y in -30..19 {
+            if world.get(x, y).element == Element::Sand {
+                assert_ne!(world.get(x, y + 1).element, Element::Air);
+            }
+        }
+    }
+}
+
+#[test]
+fn cells_are_updated_once_per_step_across_chunks() {
+    let updates = Arc::new(AtomicUsize::new(0));
+    let mut simulation = Simulation::new();
+    simulation.custom_update = Some(Arc::new(Wanderer {
+        updates: updates.clone(),
+    }));
+    let mut world = ChunkWorld::new(None);
+    for y in -10..10 {
+        for x in -10..10 {
+            if (x + y) % 3 == 0 {
+                world.set_element(x, y, Element::Custom1, 0);
+            }
+        }
+    }
+    for _ in 0..30 {
+        updates.store(0, Ordering::Relaxed);
+        world.step(&mut simulation);
+        assert_eq!(
+            updates.load(Ordering::Relaxed),
+            count(&world, Element::Custom1)
+        );
+    }
+}
+
+#[test]
+fn tools_work_across_chunks() {
+    let mut world = ChunkWorld::new(None);
+    let mut toolbox = ToolBox {
+        tool: Tool::Square,
+        element: Element::Rock,
+        tool_size: 8,
+        ..Default::default()
+    };
+    world.apply_tool(&mut toolbox, 0, 0, false);
+    assert_eq!(world.chunk_positions().count(), 4);
+    for (x, y) in [(-4, -4), (3, 3), (-4, 3), (3, -4)] {
+        assert_eq!(world.get(x, y).element, Element::Rock);
+    }
+    assert_eq!(count(&world, Element::Rock), 64);
+
+    world.apply_tool(&mut toolbox, 1, 1, true);
+    assert_eq!(count(&world, Element::Rock), 64 - 49);
+}
+
+#[test]
+fn far_chunks_are_stored_and_read_back() {
+    let directory = env::temp_dir().join(format!("falling-rust-world-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    let mut world = ChunkWorld::new(Some(directory.clone()));
+    let size = WORLD_CHUNK_SIZE as i64;
+    sand_on_rock(&mut world, 10 * size + 30, 10 * size);
+    world.set_element(-5, -5, Element::Rock, 0);
+    let mut simulation = Simulation::new();
+    for _ in 0..300 {
+        world.step(&mut simulation);
+    }
+    let sand = count(&world, Element::Sand);
+
+    // Only the chunks in and near the view stay in memory
+    world.store_far_chunks((-1, -1), (0, 0));
+    assert!(world.chunk((-1, -1)).is_some());
+    assert!(world.chunk((10, 10)).is_none());
+    assert!(world.is_stored((10, 10)));
+    assert_eq!(count(&world, Element::Sand), 0);
+
+    world.load_area((10, 10), (10, 10));
+    assert!(world.chunk((10, 10)).is_some());
+    assert_eq!(count(&world, Element::Sand), sand);
+
+    // The world continues where it was in a later run
+    world.store_all();
+    let mut world = ChunkWorld::new(Some(directory.clone()));
+    assert!(world.is_stored((-1, -1)));
+    world.load_area((-1, -1), (11, 11));
+    assert_eq!(world.get(-5, -5).element, Element::Rock);
+    assert_eq!(count(&world, Element::Sand), sand);
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn accounts_for_mass_across_chunks() {
+    let mass = |world: &ChunkWorld| {
+        let cells = world.chunk_positions().count() * WORLD_CHUNK_SIZE * WORLD_CHUNK_SIZE;
+        (cells - count(world, Element::Air)) as i64
+    };
+    let elements = || (0..ELEMENT_COUNT as u8).filter_map(|value| Element::try_from(value).ok());
+    // Reactions that create and destroy mass, on both sides of the edges between chunks
+    let mut world = ChunkWorld::new(None);
+    for x in -20..20 {
+        world.set_element(x, 20, Element::Drain, 0);
+    }
+    world.set_element(-1, 10, Element::WaterSource, 0);
+    world.set_element(1, -5, Element::TNT, 0);
+    world.set_element(1, -4, Element::Fire, 0);
+
+    let mut simulation = Simulation::new();
+    simulation.mass_balance = Some(MassBalance::new(false));
+    for _ in 0..100 {
+        let before = mass(&world);
+        world.step(&mut simulation);
+        let mass_balance = simulation.mass_balance.as_ref().unwrap();
+        let created: i64 = elements()
+            .map(|element| mass_balance.created(element) as i64)
+            .sum();
+        assert_eq!(mass(&world) - before, created);
+    }
+
+    // Preventing it keeps the mass the same
+    simulation.mass_balance = Some(MassBalance::new(true));
+    let start = mass(&world);
+    for _ in 0..100 {
+        world.step(&mut simulation);
+        assert_eq!(mass(&world), start);
+    }
+}